@@ -0,0 +1,63 @@
+// the bvh against checking every mirror, on the bundled scene. run it with
+// cargo bench --bench bvh
+use piston2d_testing::bvh::MirrorBvh;
+use piston2d_testing::{io, physics, Ray};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::time::Instant;
+
+// rays from random points inside the bundled box, in random directions
+fn random_rays(count: usize, seed: u64) -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| {
+            let angle: f64 = rng.random_range(0.0..std::f64::consts::TAU);
+            Ray::new(
+                [rng.random_range(1.0..1919.0), rng.random_range(1.0..1199.0)],
+                [angle.cos(), angle.sin()],
+                [1.0; 4],
+            )
+        })
+        .collect()
+}
+
+fn main() {
+    let scene = io::read_json("assets/mirrors.json").unwrap();
+    let mirrors = physics::mirrors_from_json(&scene, [1920.0, 1200.0]).unwrap();
+    let rays = random_rays(5000, 11);
+
+    let build_start = Instant::now();
+    let bvh = MirrorBvh::build(&mirrors);
+    let build_time = build_start.elapsed();
+
+    let rounds = 20;
+    let brute_start = Instant::now();
+    let mut brute_hits = 0;
+    for _ in 0..rounds {
+        for ray in &rays {
+            brute_hits += physics::closest_hit_brute_force(ray, &mirrors).is_some() as usize;
+        }
+    }
+    let brute_time = brute_start.elapsed();
+
+    let bvh_start = Instant::now();
+    let mut bvh_hits = 0;
+    for _ in 0..rounds {
+        for ray in &rays {
+            bvh_hits += bvh.closest_hit(ray, &mirrors).is_some() as usize;
+        }
+    }
+    let bvh_time = bvh_start.elapsed();
+
+    assert_eq!(brute_hits, bvh_hits);
+    println!(
+        "{} mirrors, {} rays x {} rounds\nbvh build: {:?}\nbrute force: {:?}\nbvh: {:?} ({:.1}x faster)",
+        mirrors.len(),
+        rays.len(),
+        rounds,
+        build_time,
+        brute_time,
+        bvh_time,
+        brute_time.as_secs_f64() / bvh_time.as_secs_f64()
+    );
+}
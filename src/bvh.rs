@@ -0,0 +1,249 @@
+use crate::physics::{Hit, Mirror, Ray, EPSILON};
+
+// mirrors per leaf. small leaves mean more boxes to test, big ones more mirrors
+const LEAF_SIZE: usize = 4;
+
+// boxes get grown by this much so hits on a box edge, or up to EPSILON past a mirror end,
+// are never lost to rounding
+const BOUNDS_PADDING: f64 = 2.0 * EPSILON;
+
+#[derive(Debug, Copy, Clone)]
+struct Node {
+    // [min_x, min_y, max_x, max_y] around everything below this node
+    bounds: [f64; 4],
+    // leaves point at a run of `mirror_indices`, inner nodes at their two children
+    kind: NodeKind,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum NodeKind {
+    Leaf { first: usize, count: usize },
+    Inner { left: usize, right: usize },
+}
+
+// bounding volume hierarchy over the mirrors of a scene. build it once per scene load
+// (the mirrors move when the window gets resized, so rebuild then as well)
+#[derive(Debug, Clone)]
+pub struct MirrorBvh {
+    nodes: Vec<Node>,
+    mirror_indices: Vec<usize>,
+}
+
+impl MirrorBvh {
+    pub fn build(mirrors: &[Mirror]) -> MirrorBvh {
+        let mut bvh = MirrorBvh {
+            nodes: Vec::new(),
+            mirror_indices: (0..mirrors.len()).collect(),
+        };
+        if mirrors.is_empty() {
+            return bvh;
+        }
+
+        let bounds: Vec<[f64; 4]> = mirrors
+            .iter()
+            .map(|mirror| {
+                let b = mirror.bounds();
+                [
+                    b[0] - BOUNDS_PADDING,
+                    b[1] - BOUNDS_PADDING,
+                    b[2] + BOUNDS_PADDING,
+                    b[3] + BOUNDS_PADDING,
+                ]
+            })
+            .collect();
+        bvh.build_node(&bounds, 0, mirrors.len());
+        bvh
+    }
+
+    // build the node for mirror_indices[first..first + count] and return its index
+    fn build_node(&mut self, bounds: &[[f64; 4]], first: usize, count: usize) -> usize {
+        let indices = &mut self.mirror_indices[first..first + count];
+        let node_bounds = indices.iter().fold(
+            [
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ],
+            |acc, &i| union(acc, bounds[i]),
+        );
+
+        let node_index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds: node_bounds,
+            kind: NodeKind::Leaf { first, count },
+        });
+        if count <= LEAF_SIZE {
+            return node_index;
+        }
+
+        // split at the median along the axis where the mirror centers spread the most
+        let center = |i: usize, axis: usize| (bounds[i][axis] + bounds[i][axis + 2]) / 2f64;
+        let spread = |axis: usize| {
+            let (low, high) = indices
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |acc, &i| {
+                    (acc.0.min(center(i, axis)), acc.1.max(center(i, axis)))
+                });
+            high - low
+        };
+        let axis = if spread(0) >= spread(1) { 0 } else { 1 };
+        indices.sort_by(|&a, &b| center(a, axis).total_cmp(&center(b, axis)));
+
+        let half = count / 2;
+        let left = self.build_node(bounds, first, half);
+        let right = self.build_node(bounds, first + half, count - half);
+        self.nodes[node_index].kind = NodeKind::Inner { left, right };
+        node_index
+    }
+
+    // same answer as physics::closest_hit_brute_force, including which mirror wins a tie
+    pub fn closest_hit(&self, ray: &Ray, mirrors: &[Mirror]) -> Option<Hit> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let speed = (ray.vector[0].powi(2) + ray.vector[1].powi(2)).sqrt();
+        let mut closest: Option<Hit> = None;
+
+        // stack of (node, distance at which the ray enters its box)
+        let mut stack: Vec<(usize, f64)> = Vec::with_capacity(32);
+        if let Some(entry) = entry_distance(self.nodes[0].bounds, ray, speed) {
+            stack.push((0, entry));
+        }
+
+        while let Some((node_index, entry)) = stack.pop() {
+            // a box entered further away than the best hit cannot hold a closer one.
+            // equal distances still get checked so ties resolve like the brute force loop
+            if closest.is_some_and(|hit| entry > hit.distance) {
+                continue;
+            }
+            match self.nodes[node_index].kind {
+                NodeKind::Leaf { first, count } => {
+                    for &index in &self.mirror_indices[first..first + count] {
+                        if let Some((position, distance)) = mirrors[index].hit(ray) {
+                            let better = match closest {
+                                None => true,
+                                Some(hit) => {
+                                    distance < hit.distance
+                                        || (distance == hit.distance && index < hit.mirror)
+                                }
+                            };
+                            if better {
+                                closest = Some(Hit {
+                                    mirror: index,
+                                    position,
+                                    distance,
+                                });
+                            }
+                        }
+                    }
+                }
+                NodeKind::Inner { left, right } => {
+                    let left_entry = entry_distance(self.nodes[left].bounds, ray, speed);
+                    let right_entry = entry_distance(self.nodes[right].bounds, ray, speed);
+                    // push the farther child first so the nearer one gets visited first
+                    match (left_entry, right_entry) {
+                        (Some(l), Some(r)) if l <= r => {
+                            stack.push((right, r));
+                            stack.push((left, l));
+                        }
+                        (Some(l), Some(r)) => {
+                            stack.push((left, l));
+                            stack.push((right, r));
+                        }
+                        (Some(l), None) => stack.push((left, l)),
+                        (None, Some(r)) => stack.push((right, r)),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+fn union(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+    [
+        a[0].min(b[0]),
+        a[1].min(b[1]),
+        a[2].max(b[2]),
+        a[3].max(b[3]),
+    ]
+}
+
+// slab test: how far along the ray it enters the box, or None if it misses it.
+// a ray starting inside the box enters at distance 0
+fn entry_distance(bounds: [f64; 4], ray: &Ray, speed: f64) -> Option<f64> {
+    let mut t_enter: f64 = 0.0;
+    let mut t_exit = f64::INFINITY;
+    for axis in 0..2 {
+        let origin = ray.start_pos[axis];
+        let direction = ray.vector[axis];
+        let (low, high) = (bounds[axis], bounds[axis + 2]);
+        if direction == 0.0 {
+            // parallel to this slab, either always inside it or never
+            if origin < low || origin > high {
+                return None;
+            }
+            continue;
+        }
+        let t_low = (low - origin) / direction;
+        let t_high = (high - origin) / direction;
+        t_enter = t_enter.max(t_low.min(t_high));
+        t_exit = t_exit.min(t_low.max(t_high));
+    }
+    if t_enter <= t_exit {
+        Some(t_enter * speed)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io, physics};
+    use rand::prelude::*;
+    use rand::rngs::StdRng;
+
+    fn bundled_mirrors() -> Vec<Mirror> {
+        let scene = io::read_json("assets/mirrors.json").unwrap();
+        physics::mirrors_from_json(&scene, [1920.0, 1200.0]).unwrap()
+    }
+
+    // rays from random points inside the bundled box, in random directions
+    fn random_rays(count: usize, seed: u64) -> Vec<Ray> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..count)
+            .map(|_| {
+                let angle: f64 = rng.random_range(0.0..std::f64::consts::TAU);
+                Ray {
+                    start_pos: [rng.random_range(1.0..1919.0), rng.random_range(1.0..1199.0)],
+                    vector: [angle.cos(), angle.sin()],
+                    color: [1.0; 4],
+                    energy: 1.0,
+                    wavelength: None,
+                    bounces: 0,
+                    id: 0,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_brute_force_on_bundled_scene() {
+        let mirrors = bundled_mirrors();
+        let bvh = MirrorBvh::build(&mirrors);
+        let mut rays = random_rays(2000, 7);
+        rays.extend(physics::generate_rays(500.0, [960.0, 600.0]));
+
+        for ray in &rays {
+            assert_eq!(
+                bvh.closest_hit(ray, &mirrors),
+                physics::closest_hit_brute_force(ray, &mirrors),
+                "ray {:?}",
+                ray
+            );
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use piston2d_testing::raster::ToneMap;
+use piston2d_testing::tracer;
+
+/// Trace light bouncing between mirrors, in a window or headless.
+#[derive(Parser, Debug)]
+#[command(version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // without a subcommand the interactive view runs with these flags
+    #[command(flatten)]
+    pub view: ViewArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Open the interactive piston window (the default)
+    ///
+    /// Space moves the light source to the cursor, R reloads the scene file and E toggles
+    /// edit mode. T switches the tone map, + and - double and halve the exposure, M writes
+    /// what the detectors measured so far to --measure-output. P pauses and resumes the
+    /// trace, N traces one more generation while paused. In edit mode drag on empty space
+    /// to place a mirror, drag a mirror's points or a light to move them, click a mirror to
+    /// select it, Delete removes the selection, Up and Down change its absorption, F moves
+    /// its reflective side (both, left, right), Ctrl+Z and Ctrl+Shift+Z undo and redo and
+    /// Ctrl+S writes the scene back to its file. Ctrl+Shift+S saves the whole session, see
+    /// --session.
+    View(ViewArgs),
+    /// Trace a scene and draw it to a png, no window needed
+    Render(RenderArgs),
+    /// Trace a scene and write every traced segment to a file
+    Trace(TraceFileArgs),
+    /// Trace a scene and write what its detectors measured to a file
+    Measure(MeasureArgs),
+    /// Trace a scene and write every hit of every ray to a file, to follow single rays
+    Paths(PathsArgs),
+    /// Check or convert scene files
+    Scene(SceneArgs),
+}
+
+// settings shared by everything that traces
+#[derive(Args, Debug, Clone)]
+pub struct TraceArgs {
+    /// Scene file to load
+    #[arg(long, default_value = "assets/mirrors.json")]
+    pub scene: String,
+    /// Number of rays the source emits
+    #[arg(long, default_value_t = 5000)]
+    pub rays: usize,
+    /// Bounce generations to trace
+    #[arg(long, default_value_t = 50)]
+    pub max_reflections: usize,
+    /// Rays with less than this fraction of their energy left stop bouncing
+    #[arg(long, default_value_t = 0.01)]
+    pub energy_threshold: f64,
+    /// Threads used for tracing, 0 uses every core and 1 traces serially
+    #[arg(long, default_value_t = 0)]
+    pub threads: usize,
+    /// Seed for the random directions of diffuse and glossy surfaces
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Most rays kept alive at once, the weakest ones are dropped past that
+    #[arg(long, default_value_t = tracer::DEFAULT_MAX_RAYS,
+          value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_rays: usize,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ViewArgs {
+    #[command(flatten)]
+    pub trace: TraceArgs,
+    /// Window width in pixels
+    #[arg(long, default_value_t = 800)]
+    pub width: u32,
+    /// Window height in pixels
+    #[arg(long, default_value_t = 800)]
+    pub height: u32,
+    /// How many scene edits can be undone
+    #[arg(long, default_value_t = piston2d_testing::history::DEFAULT_DEPTH)]
+    pub history_depth: usize,
+    /// Color of the rays as red, green, blue and alpha from 0 to 1
+    #[arg(long, num_args = 4, value_names = ["R", "G", "B", "A"],
+          default_values_t = piston2d_testing::raster::RAY_COLOR)]
+    pub ray_color: Vec<f32>,
+    #[command(flatten)]
+    pub tone: ToneArgs,
+    /// File M writes the detector profiles to, json if it ends in .json, csv otherwise
+    #[arg(long, default_value = "detectors.csv")]
+    pub measure_output: String,
+    /// Session file to save to with Ctrl+Shift+S. If it exists it is opened instead of
+    /// the scene and the settings above
+    #[arg(long)]
+    pub session: Option<String>,
+}
+
+// how gathered light becomes brightness, for the window and for images
+#[derive(Args, Debug, Clone)]
+pub struct ToneArgs {
+    /// Tone map: linear, log or reinhard
+    #[arg(long, default_value_t = ToneMap::default())]
+    pub tone_map: ToneMap,
+    /// Brightness multiplier applied before the tone map
+    #[arg(long, default_value_t = 1.0)]
+    pub exposure: f64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    pub trace: TraceArgs,
+    /// Position of the light source, defaults to the center of the image
+    #[arg(long, num_args = 2, value_names = ["X", "Y"], allow_negative_numbers = true)]
+    pub source: Option<Vec<f64>>,
+    /// Image width in pixels
+    #[arg(long, default_value_t = 800)]
+    pub width: usize,
+    /// Image height in pixels
+    #[arg(long, default_value_t = 800)]
+    pub height: usize,
+    #[command(flatten)]
+    pub tone: ToneArgs,
+    /// Png file to write
+    #[arg(short, long)]
+    pub output: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TraceFileArgs {
+    #[command(flatten)]
+    pub trace: TraceArgs,
+    /// Position of the light source, defaults to the center of the window
+    #[arg(long, num_args = 2, value_names = ["X", "Y"], allow_negative_numbers = true)]
+    pub source: Option<Vec<f64>>,
+    /// Window width the scene coordinates are resolved against
+    #[arg(long, default_value_t = 800)]
+    pub width: usize,
+    /// Window height the scene coordinates are resolved against
+    #[arg(long, default_value_t = 800)]
+    pub height: usize,
+    /// File to write the segments to
+    #[arg(short, long)]
+    pub output: String,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = TraceFormat::Json)]
+    pub format: TraceFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct MeasureArgs {
+    #[command(flatten)]
+    pub trace: TraceArgs,
+    /// Position of the light source, defaults to the center of the window
+    #[arg(long, num_args = 2, value_names = ["X", "Y"], allow_negative_numbers = true)]
+    pub source: Option<Vec<f64>>,
+    /// Window width the scene coordinates are resolved against
+    #[arg(long, default_value_t = 800)]
+    pub width: usize,
+    /// Window height the scene coordinates are resolved against
+    #[arg(long, default_value_t = 800)]
+    pub height: usize,
+    /// File to write the measurements to
+    #[arg(short, long)]
+    pub output: String,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = TraceFormat::Csv)]
+    pub format: TraceFormat,
+    /// Write every single hit instead of the binned irradiance profiles
+    #[arg(long)]
+    pub hits: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PathsArgs {
+    #[command(flatten)]
+    pub trace: TraceArgs,
+    /// Position of the light source, defaults to the center of the window
+    #[arg(long, num_args = 2, value_names = ["X", "Y"], allow_negative_numbers = true)]
+    pub source: Option<Vec<f64>>,
+    /// Window width the scene coordinates are resolved against
+    #[arg(long, default_value_t = 800)]
+    pub width: usize,
+    /// Window height the scene coordinates are resolved against
+    #[arg(long, default_value_t = 800)]
+    pub height: usize,
+    /// File to write the paths to
+    #[arg(short, long)]
+    pub output: String,
+    /// Output format. Binary writes a fixed size record per event, laid out as described
+    /// in src/paths.rs
+    #[arg(long, value_enum, default_value_t = PathFormat::Csv)]
+    pub format: PathFormat,
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PathFormat {
+    Csv,
+    Jsonl,
+    Binary,
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Args, Debug)]
+pub struct SceneArgs {
+    #[command(subcommand)]
+    pub command: SceneCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SceneCommand {
+    /// Load a scene and report every problem in it
+    Validate {
+        /// Scene file to check
+        path: String,
+        /// Window width the scene coordinates are resolved against
+        #[arg(long, default_value_t = 800.0)]
+        width: f64,
+        /// Window height the scene coordinates are resolved against
+        #[arg(long, default_value_t = 800.0)]
+        height: f64,
+    },
+    /// Rewrite a scene in another coordinate format, optionally moving it around
+    Convert {
+        /// Scene file to read
+        input: String,
+        /// Scene file to write, has to be a different file than the input
+        output: String,
+        /// Coordinate format to write: pixels, fractions or aspect_fractions.
+        /// Keeps the format of the input if left out
+        #[arg(long)]
+        to: Option<String>,
+        /// Reference window width for the conversion
+        #[arg(long, default_value_t = 800.0)]
+        width: f64,
+        /// Reference window height for the conversion
+        #[arg(long, default_value_t = 800.0)]
+        height: f64,
+        /// Scale the scene, one factor for both axes or separate x and y factors
+        #[arg(long, num_args = 1..=2, value_names = ["X", "Y"])]
+        scale: Option<Vec<f64>>,
+        /// Rotate the scene by this many degrees, clockwise on screen
+        #[arg(long, allow_negative_numbers = true)]
+        rotate: Option<f64>,
+        /// Move the scene by this many reference pixels, after scaling and rotating
+        #[arg(long, num_args = 2, value_names = ["X", "Y"], allow_negative_numbers = true)]
+        translate: Option<Vec<f64>>,
+        /// Point to scale and rotate around in reference pixels, defaults to the center
+        #[arg(long, num_args = 2, value_names = ["X", "Y"], allow_negative_numbers = true)]
+        origin: Option<Vec<f64>>,
+    },
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::str::FromStr;
+
+// the coordinate systems a scene file can be written in.
+// every format gets resolved to window pixels before the physics sees it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CoordFormat {
+    // absolute window pixels, used as is
+    Pixels,
+    // fractions of the window, x is scaled by the width and y by the height
+    Fractions,
+    // fractions of the shorter window side, so the scene keeps its shape on any window
+    AspectFractions,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownCoordFormat(pub String);
+
+impl fmt::Display for UnknownCoordFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown coord_format \"{}\" (expected \"pixels\", \"fractions\" or \"aspect_fractions\")",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownCoordFormat {}
+
+impl FromStr for CoordFormat {
+    type Err = UnknownCoordFormat;
+
+    fn from_str(s: &str) -> Result<CoordFormat, UnknownCoordFormat> {
+        match s {
+            "pixels" => Ok(CoordFormat::Pixels),
+            "fractions" => Ok(CoordFormat::Fractions),
+            "aspect_fractions" => Ok(CoordFormat::AspectFractions),
+            other => Err(UnknownCoordFormat(other.to_string())),
+        }
+    }
+}
+
+impl CoordFormat {
+    // the name used for this format in scene files
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoordFormat::Pixels => "pixels",
+            CoordFormat::Fractions => "fractions",
+            CoordFormat::AspectFractions => "aspect_fractions",
+        }
+    }
+
+    // turn a point from this format into window pixels
+    pub fn resolve(&self, point: [f64; 2], window_dimensions: [f64; 2]) -> [f64; 2] {
+        match self {
+            CoordFormat::Pixels => point,
+            CoordFormat::Fractions => [
+                point[0] * window_dimensions[0],
+                point[1] * window_dimensions[1],
+            ],
+            CoordFormat::AspectFractions => {
+                let scale = window_dimensions[0].min(window_dimensions[1]);
+                [point[0] * scale, point[1] * scale]
+            }
+        }
+    }
+
+    // turn a point in window pixels into this format, the opposite of resolve
+    pub fn unresolve(&self, point: [f64; 2], window_dimensions: [f64; 2]) -> [f64; 2] {
+        match self {
+            CoordFormat::Pixels => point,
+            CoordFormat::Fractions => [
+                point[0] / window_dimensions[0],
+                point[1] / window_dimensions[1],
+            ],
+            CoordFormat::AspectFractions => {
+                let scale = window_dimensions[0].min(window_dimensions[1]);
+                [point[0] / scale, point[1] / scale]
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::physics::{Mirror, Ray, Surface};
+use serde::Serialize;
+
+// pieces a detector's profile is split into unless the scene says otherwise
+pub const DEFAULT_BINS: usize = 32;
+
+// one ray arriving at a detector
+#[derive(Serialize, Debug, Copy, Clone, PartialEq)]
+pub struct DetectorHit {
+    // index of the detector in the mirror vec
+    pub detector: usize,
+    // where along the detector, 0 at start_pos and 1 at end_pos
+    pub position: f64,
+    // degrees between the ray and the detector normal, positive when the ray runs
+    // towards end_pos. the same on either side of the detector
+    pub angle: f64,
+    pub energy: f64,
+    // surfaces the ray bounced off or went through before it got here
+    pub bounces: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wavelength: Option<f64>,
+}
+
+impl DetectorHit {
+    // the hit of `ray` on the detector `mirror` (mirror number `index`) at `position`
+    pub fn new(index: usize, mirror: &Mirror, ray: &Ray, position: [f64; 2]) -> DetectorHit {
+        let along = [
+            mirror.end_pos[0] - mirror.start_pos[0],
+            mirror.end_pos[1] - mirror.start_pos[1],
+        ];
+        let length_sq = along[0] * along[0] + along[1] * along[1];
+        let offset = [
+            position[0] - mirror.start_pos[0],
+            position[1] - mirror.start_pos[1],
+        ];
+        let fraction = (offset[0] * along[0] + offset[1] * along[1]) / length_sq;
+
+        // both parts of the ray direction, relative to the detector's own length
+        let tangential = ray.vector[0] * along[0] + ray.vector[1] * along[1];
+        let normal = ray.vector[0] * along[1] - ray.vector[1] * along[0];
+        DetectorHit {
+            detector: index,
+            position: fraction.clamp(0.0, 1.0),
+            angle: tangential.atan2(normal.abs()).to_degrees(),
+            energy: ray.energy,
+            bounces: ray.bounces,
+            wavelength: ray.wavelength,
+        }
+    }
+}
+
+// the light a detector got, binned along its length
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub detector: usize,
+    // length of the detector in pixels
+    pub length: f64,
+    pub hits: usize,
+    pub energy: f64,
+    pub bins: Vec<Bin>,
+}
+
+#[derive(Serialize, Debug, Copy, Clone, PartialEq)]
+pub struct Bin {
+    // the stretch of the detector this bin covers, as fractions like DetectorHit::position
+    pub start: f64,
+    pub end: f64,
+    pub hits: usize,
+    pub energy: f64,
+    // energy per pixel of detector length
+    pub irradiance: f64,
+}
+
+// one profile for every detector in the scene, in mirror order, with the hits sorted into
+// their bins. detectors nothing arrived at get a profile of empty bins
+pub fn profiles(mirrors: &[Mirror], hits: &[DetectorHit]) -> Vec<Profile> {
+    let mut profiles: Vec<Profile> = Vec::new();
+    // where each mirror's profile sits in `profiles`
+    let mut slots: Vec<Option<usize>> = vec![None; mirrors.len()];
+    for (index, mirror) in mirrors.iter().enumerate() {
+        let Surface::Detector { bins } = mirror.surface else {
+            continue;
+        };
+        let bins = bins.max(1);
+        let length = ((mirror.end_pos[0] - mirror.start_pos[0]).powi(2)
+            + (mirror.end_pos[1] - mirror.start_pos[1]).powi(2))
+        .sqrt();
+        slots[index] = Some(profiles.len());
+        profiles.push(Profile {
+            detector: index,
+            length,
+            hits: 0,
+            energy: 0.0,
+            bins: (0..bins)
+                .map(|bin| Bin {
+                    start: bin as f64 / bins as f64,
+                    end: (bin + 1) as f64 / bins as f64,
+                    hits: 0,
+                    energy: 0.0,
+                    irradiance: 0.0,
+                })
+                .collect(),
+        });
+    }
+
+    for hit in hits {
+        let Some(slot) = slots.get(hit.detector).copied().flatten() else {
+            continue;
+        };
+        let profile = &mut profiles[slot];
+        let count = profile.bins.len();
+        // a hit right on end_pos belongs to the last bin
+        let bin = ((hit.position * count as f64) as usize).min(count - 1);
+        profile.hits += 1;
+        profile.energy += hit.energy;
+        profile.bins[bin].hits += 1;
+        profile.bins[bin].energy += hit.energy;
+    }
+    for profile in &mut profiles {
+        let bin_length = profile.length / profile.bins.len() as f64;
+        for bin in &mut profile.bins {
+            bin.irradiance = bin.energy / bin_length;
+        }
+    }
+    profiles
+}
+
+// the profiles as csv, one line per bin
+pub fn profiles_csv(profiles: &[Profile]) -> String {
+    let mut data = String::from("detector,bin,start,end,hits,energy,irradiance\n");
+    for profile in profiles {
+        for (n, bin) in profile.bins.iter().enumerate() {
+            data.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                profile.detector, n, bin.start, bin.end, bin.hits, bin.energy, bin.irradiance
+            ));
+        }
+    }
+    data
+}
+
+// every single hit as csv. rays without a wavelength leave the last column empty
+pub fn hits_csv(hits: &[DetectorHit]) -> String {
+    let mut data = String::from("detector,position,angle,energy,bounces,wavelength\n");
+    for hit in hits {
+        let wavelength = hit.wavelength.map_or(String::new(), |w| w.to_string());
+        data.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            hit.detector, hit.position, hit.angle, hit.energy, hit.bounces, wavelength
+        ));
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::{Shape, Sides};
+
+    // a vertical detector from y = 0 to y = 100, split into 4 bins
+    fn screen() -> Mirror {
+        Mirror {
+            start_pos: [50.0, 0.0],
+            end_pos: [50.0, 100.0],
+            color: [1.0; 4],
+            absorption_factor: 1.0,
+            surface: Surface::Detector { bins: 4 },
+            shape: Shape::Segment,
+            sides: Sides::Both,
+        }
+    }
+
+    #[test]
+    fn hits_know_where_and_how_they_arrived() {
+        let ray = Ray {
+            bounces: 3,
+            energy: 0.5,
+            ..Ray::new([0.0, 0.0], [1.0, 1.0], [1.0; 4])
+        };
+        let hit = DetectorHit::new(7, &screen(), &ray, [50.0, 50.0]);
+        assert_eq!(hit.detector, 7);
+        assert_eq!(hit.position, 0.5);
+        // 45 degrees off the normal, running towards end_pos
+        assert!((hit.angle - 45.0).abs() < 1e-9);
+        assert_eq!((hit.energy, hit.bounces), (0.5, 3));
+
+        // coming from the other side, running towards start_pos
+        let back = Ray::new([100.0, 30.0], [-1.0, -1.0], [1.0; 4]);
+        let hit = DetectorHit::new(7, &screen(), &back, [50.0, 25.0]);
+        assert_eq!(hit.position, 0.25);
+        assert!((hit.angle + 45.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn profiles_bin_hits_along_the_detector() {
+        let ray = Ray::new([0.0, 0.0], [1.0, 0.0], [1.0; 4]);
+        let mirrors = [
+            Mirror {
+                surface: Surface::Mirror,
+                ..screen()
+            },
+            screen(),
+        ];
+        let hits: Vec<DetectorHit> = [10.0, 20.0, 60.0, 100.0]
+            .iter()
+            .map(|&y| DetectorHit::new(1, &mirrors[1], &ray, [50.0, y]))
+            .collect();
+        let profiles = profiles(&mirrors, &hits);
+
+        // only the detector gets a profile
+        assert_eq!(profiles.len(), 1);
+        let profile = &profiles[0];
+        assert_eq!(
+            (profile.detector, profile.hits, profile.energy),
+            (1, 4, 4.0)
+        );
+        let counts: Vec<usize> = profile.bins.iter().map(|bin| bin.hits).collect();
+        // the hit on end_pos counts towards the last bin
+        assert_eq!(counts, vec![2, 0, 1, 1]);
+        // bins are 25 pixels long
+        assert_eq!(profile.bins[0].irradiance, 2.0 / 25.0);
+
+        let csv = profiles_csv(&profiles);
+        assert_eq!(csv.lines().count(), 1 + 4);
+        assert!(csv.lines().nth(1).unwrap().starts_with("1,0,0,0.25,2,2,"));
+        assert_eq!(hits_csv(&hits).lines().count(), 1 + 4);
+    }
+}
@@ -0,0 +1,608 @@
+use crate::coords::CoordFormat;
+use crate::history::{Edit, History};
+use crate::io;
+use crate::scene::Scene;
+use serde::{Deserialize, Serialize};
+
+// how close, in pixels, the cursor has to be to grab a point or pick a mirror
+pub const GRAB_RADIUS: f64 = 8.0;
+
+// how much one key press changes the absorption of the selected mirror
+pub const ABSORPTION_STEP: f64 = 0.05;
+
+// drags shorter than this (in pixels) count as a click and do not place a mirror
+pub const MIN_SEGMENT_LENGTH: f64 = 3.0;
+
+// one of the points that define a mirror in the scene file
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Handle {
+    Start,
+    End,
+    Through,
+    Vertex,
+    Focus,
+}
+
+impl Handle {
+    pub const ALL: [Handle; 5] = [
+        Handle::Start,
+        Handle::End,
+        Handle::Through,
+        Handle::Vertex,
+        Handle::Focus,
+    ];
+
+    // the scene file field this handle edits
+    pub fn field(&self) -> &'static str {
+        match self {
+            Handle::Start => "start_pos",
+            Handle::End => "end_pos",
+            Handle::Through => "through_pos",
+            Handle::Vertex => "vertex",
+            Handle::Focus => "focus",
+        }
+    }
+
+    pub fn get<'a>(&self, mirror: &'a io::Mirror) -> Option<&'a Vec<f64>> {
+        match self {
+            Handle::Start => Some(&mirror.start_pos),
+            Handle::End => Some(&mirror.end_pos),
+            Handle::Through => mirror.through_pos.as_ref(),
+            Handle::Vertex => mirror.vertex.as_ref(),
+            Handle::Focus => mirror.focus.as_ref(),
+        }
+    }
+
+    pub fn get_mut<'a>(&self, mirror: &'a mut io::Mirror) -> Option<&'a mut Vec<f64>> {
+        match self {
+            Handle::Start => Some(&mut mirror.start_pos),
+            Handle::End => Some(&mut mirror.end_pos),
+            Handle::Through => mirror.through_pos.as_mut(),
+            Handle::Vertex => mirror.vertex.as_mut(),
+            Handle::Focus => mirror.focus.as_mut(),
+        }
+    }
+}
+
+// what the left mouse button is currently dragging
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drag {
+    // a new mirror from where the button went down to the cursor, in window pixels
+    Placing {
+        start: [f64; 2],
+        end: [f64; 2],
+    },
+    // a point of an existing mirror following the cursor. `from` is where it started,
+    // in the coordinates of the scene file
+    Moving {
+        mirror: usize,
+        handle: Handle,
+        from: [f64; 2],
+    },
+    // a whole light following the cursor. `anchor` is where the button went down, in
+    // window pixels, and `from` the light as it was then
+    MovingLight {
+        light: usize,
+        anchor: [f64; 2],
+        from: io::Light,
+    },
+}
+
+// what an edit did, so the window knows how much to redo
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Change {
+    Nothing,
+    // only the overlay (selection, preview line) changed
+    View,
+    // the scene file changed, re-resolve it and trace again
+    Scene,
+}
+
+// the state of edit mode. all edits go straight into the scene file, positions come in
+// as window pixels and get stored in the coord_format of the file. every finished edit
+// lands in the history
+#[derive(Debug, Clone, Default)]
+pub struct Editor {
+    pub selected: Option<usize>,
+    pub drag: Option<Drag>,
+    pub history: History,
+}
+
+impl Editor {
+    pub fn new(history_depth: usize) -> Editor {
+        Editor {
+            history: History::new(history_depth),
+            ..Editor::default()
+        }
+    }
+
+    // left button down: grab the point under the cursor, else pick the mirror under it,
+    // else start placing a new mirror
+    pub fn press(
+        &mut self,
+        scene_file: &io::JSON,
+        scene: &Scene,
+        cursor: [f64; 2],
+        window_dimensions: [f64; 2],
+    ) -> Result<Change, io::SceneError> {
+        if let Some(light) = pick_light(scene_file, cursor, window_dimensions)? {
+            self.drag = Some(Drag::MovingLight {
+                light,
+                anchor: cursor,
+                from: scene_file.lights[light].clone(),
+            });
+        } else if let Some((mirror, handle)) = pick_handle(scene_file, cursor, window_dimensions)? {
+            // pick_handle only finds points that exist and parse
+            let values = handle.get(&scene_file.mirrors[mirror]).unwrap();
+            let from = io::point(values, mirror, handle.field())?;
+            self.selected = Some(mirror);
+            self.drag = Some(Drag::Moving {
+                mirror,
+                handle,
+                from,
+            });
+        } else if let Some(mirror) = pick_mirror(scene, cursor) {
+            self.selected = Some(mirror);
+        } else {
+            self.drag = Some(Drag::Placing {
+                start: cursor,
+                end: cursor,
+            });
+        }
+        Ok(Change::View)
+    }
+
+    pub fn motion(
+        &mut self,
+        scene_file: &mut io::JSON,
+        cursor: [f64; 2],
+        window_dimensions: [f64; 2],
+    ) -> Result<Change, io::SceneError> {
+        match &mut self.drag {
+            None => Ok(Change::Nothing),
+            Some(Drag::Placing { end, .. }) => {
+                *end = cursor;
+                Ok(Change::View)
+            }
+            Some(Drag::Moving { mirror, handle, .. }) => {
+                let (mirror, handle) = (*mirror, *handle);
+                move_point(scene_file, mirror, handle, cursor, window_dimensions)?;
+                Ok(Change::Scene)
+            }
+            Some(Drag::MovingLight {
+                light,
+                anchor,
+                from,
+            }) => {
+                let coord_format: CoordFormat = scene_file.coord_format.parse()?;
+                let offset = [cursor[0] - anchor[0], cursor[1] - anchor[1]];
+                // resolving only scales, so an offset converts like a point
+                let offset = coord_format.unresolve(offset, window_dimensions);
+                if let Some(moved) = scene_file.lights.get_mut(*light) {
+                    *moved = from.translated(offset);
+                }
+                Ok(Change::Scene)
+            }
+        }
+    }
+
+    // left button up: finish the drag. a long enough placing drag becomes a mirror
+    pub fn release(
+        &mut self,
+        scene_file: &mut io::JSON,
+        window_dimensions: [f64; 2],
+    ) -> Result<Change, io::SceneError> {
+        match self.drag.take() {
+            None => Ok(Change::Nothing),
+            Some(Drag::Moving {
+                mirror,
+                handle,
+                from,
+            }) => {
+                // the whole drag is one edit, from where it started to where it ended
+                let to = handle
+                    .get(&scene_file.mirrors[mirror])
+                    .cloned()
+                    .unwrap_or_default();
+                if to != from {
+                    self.history.push(Edit::Move {
+                        mirror,
+                        handle,
+                        from: from.to_vec(),
+                        to,
+                    });
+                }
+                Ok(Change::View)
+            }
+            Some(Drag::MovingLight { light, from, .. }) => {
+                if let Some(to) = scene_file.lights.get(light) {
+                    if *to != from {
+                        self.history.push(Edit::Light {
+                            light,
+                            from,
+                            to: to.clone(),
+                        });
+                    }
+                }
+                Ok(Change::View)
+            }
+            Some(Drag::Placing { start, end }) => {
+                if distance(start, end) < MIN_SEGMENT_LENGTH {
+                    // a click on empty space clears the selection
+                    self.selected = None;
+                    return Ok(Change::View);
+                }
+                let index = insert_segment(scene_file, start, end, window_dimensions)?;
+                self.history.push(Edit::Insert {
+                    index,
+                    mirror: scene_file.mirrors[index].clone(),
+                });
+                self.selected = Some(index);
+                Ok(Change::Scene)
+            }
+        }
+    }
+
+    pub fn delete_selected(&mut self, scene_file: &mut io::JSON) -> Change {
+        match self.selected.take() {
+            Some(index) if index < scene_file.mirrors.len() => {
+                let mirror = scene_file.mirrors.remove(index);
+                self.history.push(Edit::Delete { index, mirror });
+                Change::Scene
+            }
+            _ => Change::Nothing,
+        }
+    }
+
+    // change the absorption of the selected mirror, staying inside 0 to 1
+    pub fn adjust_absorption(&mut self, scene_file: &mut io::JSON, delta: f64) -> Change {
+        let Some(mirror) = self.selected.and_then(|i| scene_file.mirrors.get_mut(i)) else {
+            return Change::Nothing;
+        };
+        let absorption = (mirror.absorption_factor + delta).clamp(0.0, 1.0);
+        // keep the value on the step grid so repeated presses do not drift
+        let absorption = (absorption / ABSORPTION_STEP).round() * ABSORPTION_STEP;
+        if absorption == mirror.absorption_factor {
+            return Change::Nothing;
+        }
+        let from = Box::new(mirror.clone());
+        mirror.absorption_factor = absorption;
+        self.history.push(Edit::Property {
+            mirror: self.selected.unwrap(),
+            from,
+            to: Box::new(mirror.clone()),
+        });
+        Change::Scene
+    }
+
+    // make the selected mirror one sided with its front on the left, then on the right,
+    // then two sided again
+    pub fn cycle_front(&mut self, scene_file: &mut io::JSON) -> Change {
+        let Some(mirror) = self.selected.and_then(|i| scene_file.mirrors.get_mut(i)) else {
+            return Change::Nothing;
+        };
+        let from = Box::new(mirror.clone());
+        mirror.front = match mirror.front {
+            None => Some(io::Side::Left),
+            Some(io::Side::Left) => Some(io::Side::Right),
+            Some(io::Side::Right) => None,
+        };
+        self.history.push(Edit::Property {
+            mirror: self.selected.unwrap(),
+            from,
+            to: Box::new(mirror.clone()),
+        });
+        Change::Scene
+    }
+
+    // take back the last edit. does nothing in the middle of a drag
+    pub fn undo(&mut self, scene_file: &mut io::JSON) -> Change {
+        if self.drag.is_some() {
+            return Change::Nothing;
+        }
+        let change = match self.history.undo(scene_file) {
+            Some(_) => Change::Scene,
+            None => Change::Nothing,
+        };
+        self.clamp_selection(scene_file);
+        change
+    }
+
+    pub fn redo(&mut self, scene_file: &mut io::JSON) -> Change {
+        if self.drag.is_some() {
+            return Change::Nothing;
+        }
+        let change = match self.history.redo(scene_file) {
+            Some(_) => Change::Scene,
+            None => Change::Nothing,
+        };
+        self.clamp_selection(scene_file);
+        change
+    }
+
+    // the line of a mirror being placed, in window pixels
+    pub fn preview(&self) -> Option<[f64; 4]> {
+        match self.drag {
+            Some(Drag::Placing { start, end }) => Some([start[0], start[1], end[0], end[1]]),
+            _ => None,
+        }
+    }
+
+    // forget selections that no longer exist, e.g. after reloading the scene
+    pub fn clamp_selection(&mut self, scene_file: &io::JSON) {
+        if self.selected.is_some_and(|i| i >= scene_file.mirrors.len()) {
+            self.selected = None;
+        }
+        self.drag = None;
+    }
+}
+
+// the points of a mirror in window pixels, with the handle each belongs to
+pub fn handles(
+    scene_file: &io::JSON,
+    mirror: usize,
+    window_dimensions: [f64; 2],
+) -> Result<Vec<(Handle, [f64; 2])>, io::SceneError> {
+    let coord_format: CoordFormat = scene_file.coord_format.parse()?;
+    let Some(json_mirror) = scene_file.mirrors.get(mirror) else {
+        return Ok(Vec::new());
+    };
+    let mut points = Vec::new();
+    for handle in Handle::ALL {
+        if let Some(values) = handle.get(json_mirror) {
+            let point = io::point(values, mirror, handle.field())?;
+            points.push((handle, coord_format.resolve(point, window_dimensions)));
+        }
+    }
+    Ok(points)
+}
+
+// the closest light within GRAB_RADIUS of the cursor. lights along a line can be grabbed
+// at either end
+pub fn pick_light(
+    scene_file: &io::JSON,
+    cursor: [f64; 2],
+    window_dimensions: [f64; 2],
+) -> Result<Option<usize>, io::SceneError> {
+    let coord_format: CoordFormat = scene_file.coord_format.parse()?;
+    let mut closest: Option<(usize, f64)> = None;
+    for (index, light) in scene_file.lights.iter().enumerate() {
+        let mut points = vec![io::light_point(&light.position, index, "position")?];
+        if let Some(end_pos) = &light.end_pos {
+            points.push(io::light_point(end_pos, index, "end_pos")?);
+        }
+        for point in points {
+            let d = distance(coord_format.resolve(point, window_dimensions), cursor);
+            if d <= GRAB_RADIUS && closest.is_none_or(|(_, best)| d < best) {
+                closest = Some((index, d));
+            }
+        }
+    }
+    Ok(closest.map(|(index, _)| index))
+}
+
+// the closest mirror point within GRAB_RADIUS of the cursor
+pub fn pick_handle(
+    scene_file: &io::JSON,
+    cursor: [f64; 2],
+    window_dimensions: [f64; 2],
+) -> Result<Option<(usize, Handle)>, io::SceneError> {
+    let mut closest: Option<(usize, Handle, f64)> = None;
+    for mirror in 0..scene_file.mirrors.len() {
+        for (handle, point) in handles(scene_file, mirror, window_dimensions)? {
+            let d = distance(point, cursor);
+            if d <= GRAB_RADIUS && closest.is_none_or(|(_, _, best)| d < best) {
+                closest = Some((mirror, handle, d));
+            }
+        }
+    }
+    Ok(closest.map(|(mirror, handle, _)| (mirror, handle)))
+}
+
+// the closest mirror within GRAB_RADIUS of the cursor, measured to its drawn outline
+pub fn pick_mirror(scene: &Scene, cursor: [f64; 2]) -> Option<usize> {
+    let mut closest: Option<(usize, f64)> = None;
+    for (index, mirror) in scene.mirrors().iter().enumerate() {
+        for line in mirror.outline() {
+            let d = distance_to_line(cursor, line);
+            if d <= GRAB_RADIUS && closest.is_none_or(|(_, best)| d < best) {
+                closest = Some((index, d));
+            }
+        }
+    }
+    closest.map(|(index, _)| index)
+}
+
+// put one point of a mirror at a window position
+pub fn move_point(
+    scene_file: &mut io::JSON,
+    mirror: usize,
+    handle: Handle,
+    position: [f64; 2],
+    window_dimensions: [f64; 2],
+) -> Result<(), io::SceneError> {
+    let coord_format: CoordFormat = scene_file.coord_format.parse()?;
+    if let Some(values) = scene_file
+        .mirrors
+        .get_mut(mirror)
+        .and_then(|m| handle.get_mut(m))
+    {
+        *values = coord_format.unresolve(position, window_dimensions).to_vec();
+    }
+    Ok(())
+}
+
+// add a perfect straight mirror between two window positions, returns its index
+pub fn insert_segment(
+    scene_file: &mut io::JSON,
+    start: [f64; 2],
+    end: [f64; 2],
+    window_dimensions: [f64; 2],
+) -> Result<usize, io::SceneError> {
+    let coord_format: CoordFormat = scene_file.coord_format.parse()?;
+    scene_file.mirrors.push(io::Mirror::segment(
+        coord_format.unresolve(start, window_dimensions),
+        coord_format.unresolve(end, window_dimensions),
+        0.0,
+    ));
+    Ok(scene_file.mirrors.len() - 1)
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+fn distance_to_line(point: [f64; 2], line: [f64; 4]) -> f64 {
+    let (start, end) = ([line[0], line[1]], [line[2], line[3]]);
+    let direction = [end[0] - start[0], end[1] - start[1]];
+    let length_sq = direction[0].powi(2) + direction[1].powi(2);
+    if length_sq == 0.0 {
+        return distance(point, start);
+    }
+    let t = (((point[0] - start[0]) * direction[0] + (point[1] - start[1]) * direction[1])
+        / length_sq)
+        .clamp(0.0, 1.0);
+    distance(
+        point,
+        [start[0] + t * direction[0], start[1] + t * direction[1]],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fractions_scene() -> io::JSON {
+        io::parse_json(
+            r#"{"version": 1, "coord_format": "fractions", "mirrors": [
+                {"start_pos": [0.25, 0.5], "end_pos": [0.75, 0.5], "absorption_factor": 0.0}
+            ]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn drags_place_and_move_mirrors_in_file_coordinates() {
+        let dimensions = [800.0, 400.0];
+        let mut scene_file = fractions_scene();
+        let mut editor = Editor::default();
+
+        // drag on empty space places a new mirror
+        let scene = Scene::from_json(&scene_file, dimensions).unwrap();
+        editor
+            .press(&scene_file, &scene, [400.0, 40.0], dimensions)
+            .unwrap();
+        editor
+            .motion(&mut scene_file, [600.0, 40.0], dimensions)
+            .unwrap();
+        assert_eq!(
+            editor.release(&mut scene_file, dimensions).unwrap(),
+            Change::Scene
+        );
+        assert_eq!(scene_file.mirrors.len(), 2);
+        assert_eq!(scene_file.mirrors[1].start_pos, vec![0.5, 0.1]);
+        assert_eq!(scene_file.mirrors[1].end_pos, vec![0.75, 0.1]);
+        assert_eq!(editor.selected, Some(1));
+
+        // grabbing the end of the first mirror drags it along
+        let scene = Scene::from_json(&scene_file, dimensions).unwrap();
+        editor
+            .press(&scene_file, &scene, [603.0, 201.0], dimensions)
+            .unwrap();
+        assert_eq!(
+            editor.drag,
+            Some(Drag::Moving {
+                mirror: 0,
+                handle: Handle::End,
+                from: [0.75, 0.5]
+            })
+        );
+        editor
+            .motion(&mut scene_file, [400.0, 300.0], dimensions)
+            .unwrap();
+        editor.release(&mut scene_file, dimensions).unwrap();
+        assert_eq!(scene_file.mirrors[0].end_pos, vec![0.5, 0.75]);
+
+        // both edits come back out of the history in reverse
+        assert_eq!(editor.undo(&mut scene_file), Change::Scene);
+        assert_eq!(scene_file.mirrors[0].end_pos, vec![0.75, 0.5]);
+        assert_eq!(editor.undo(&mut scene_file), Change::Scene);
+        assert_eq!(scene_file.mirrors.len(), 1);
+        assert_eq!(editor.undo(&mut scene_file), Change::Nothing);
+        assert_eq!(editor.redo(&mut scene_file), Change::Scene);
+        assert_eq!(scene_file.mirrors.len(), 2);
+    }
+
+    #[test]
+    fn selection_takes_deletes_and_absorption_changes() {
+        let dimensions = [800.0, 400.0];
+        let mut scene_file = fractions_scene();
+        let scene = Scene::from_json(&scene_file, dimensions).unwrap();
+        let mut editor = Editor::default();
+
+        editor
+            .press(&scene_file, &scene, [400.0, 203.0], dimensions)
+            .unwrap();
+        assert_eq!(editor.selected, Some(0));
+        assert_eq!(editor.drag, None);
+
+        for _ in 0..30 {
+            editor.adjust_absorption(&mut scene_file, ABSORPTION_STEP);
+        }
+        assert_eq!(scene_file.mirrors[0].absorption_factor, 1.0);
+        assert_eq!(
+            editor.adjust_absorption(&mut scene_file, ABSORPTION_STEP),
+            Change::Nothing
+        );
+
+        // left, right, then both sides again
+        editor.cycle_front(&mut scene_file);
+        assert_eq!(scene_file.mirrors[0].front, Some(io::Side::Left));
+        editor.cycle_front(&mut scene_file);
+        assert_eq!(scene_file.mirrors[0].front, Some(io::Side::Right));
+        editor.cycle_front(&mut scene_file);
+        assert_eq!(scene_file.mirrors[0].front, None);
+        // property edits on one mirror merge, one undo takes back the absorption too
+        editor.undo(&mut scene_file);
+        editor.cycle_front(&mut scene_file);
+        assert_eq!(
+            (
+                scene_file.mirrors[0].absorption_factor,
+                scene_file.mirrors[0].front
+            ),
+            (0.0, Some(io::Side::Left))
+        );
+
+        assert_eq!(editor.delete_selected(&mut scene_file), Change::Scene);
+        assert!(scene_file.mirrors.is_empty());
+        assert_eq!(editor.selected, None);
+    }
+
+    #[test]
+    fn lights_are_dragged_as_a_whole() {
+        let dimensions = [800.0, 400.0];
+        let mut scene_file = io::parse_json(
+            r#"{"version": 2, "coord_format": "fractions", "mirrors": [], "lights": [
+                {"kind": "line", "position": [0.25, 0.25], "end_pos": [0.5, 0.25]}
+            ]}"#,
+        )
+        .unwrap();
+        let scene = Scene::from_json(&scene_file, dimensions).unwrap();
+        let mut editor = Editor::default();
+
+        // grab the far end and drag it 80 pixels right and 40 down
+        editor
+            .press(&scene_file, &scene, [400.0, 100.0], dimensions)
+            .unwrap();
+        editor
+            .motion(&mut scene_file, [480.0, 140.0], dimensions)
+            .unwrap();
+        editor.release(&mut scene_file, dimensions).unwrap();
+        assert_eq!(scene_file.lights[0].position, vec![0.35, 0.35]);
+        assert_eq!(scene_file.lights[0].end_pos, Some(vec![0.6, 0.35]));
+
+        editor.undo(&mut scene_file);
+        assert_eq!(scene_file.lights[0].position, vec![0.25, 0.25]);
+    }
+}
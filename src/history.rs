@@ -0,0 +1,352 @@
+use crate::editor::Handle;
+use crate::io;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// how many edits can be undone unless configured otherwise
+pub const DEFAULT_DEPTH: usize = 100;
+
+// one change to a scene file, with everything needed to do it again or take it back
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Edit {
+    Insert {
+        index: usize,
+        mirror: io::Mirror,
+    },
+    Delete {
+        index: usize,
+        mirror: io::Mirror,
+    },
+    // one point of a mirror, in the coordinates of the scene file
+    Move {
+        mirror: usize,
+        handle: Handle,
+        from: Vec<f64>,
+        to: Vec<f64>,
+    },
+    // anything else about a mirror, e.g. its absorption. keeps the whole mirror both ways,
+    // boxed since mirrors with dispersive glass are large
+    Property {
+        mirror: usize,
+        from: Box<io::Mirror>,
+        to: Box<io::Mirror>,
+    },
+    // a light moved or changed, with the whole light both ways
+    Light {
+        light: usize,
+        from: io::Light,
+        to: io::Light,
+    },
+}
+
+impl Edit {
+    // do the edit. returns false if it does not fit the scene (e.g. a history loaded
+    // next to the wrong scene), the scene is left alone then
+    pub fn apply(&self, scene_file: &mut io::JSON) -> bool {
+        let mirrors = &mut scene_file.mirrors;
+        match self {
+            Edit::Light { light, to, .. } => replace(&mut scene_file.lights, *light, to),
+            Edit::Insert { index, mirror } => insert(mirrors, *index, mirror),
+            Edit::Delete { index, .. } => remove(mirrors, *index),
+            Edit::Move {
+                mirror, handle, to, ..
+            } => set_point(mirrors, *mirror, *handle, to),
+            Edit::Property { mirror, to, .. } => replace(mirrors, *mirror, to),
+        }
+    }
+
+    // take the edit back, the opposite of apply
+    pub fn revert(&self, scene_file: &mut io::JSON) -> bool {
+        let mirrors = &mut scene_file.mirrors;
+        match self {
+            Edit::Light { light, from, .. } => replace(&mut scene_file.lights, *light, from),
+            Edit::Insert { index, .. } => remove(mirrors, *index),
+            Edit::Delete { index, mirror } => insert(mirrors, *index, mirror),
+            Edit::Move {
+                mirror,
+                handle,
+                from,
+                ..
+            } => set_point(mirrors, *mirror, *handle, from),
+            Edit::Property { mirror, from, .. } => replace(mirrors, *mirror, from),
+        }
+    }
+}
+
+fn insert(mirrors: &mut Vec<io::Mirror>, index: usize, mirror: &io::Mirror) -> bool {
+    if index > mirrors.len() {
+        return false;
+    }
+    mirrors.insert(index, mirror.clone());
+    true
+}
+
+fn remove(mirrors: &mut Vec<io::Mirror>, index: usize) -> bool {
+    if index >= mirrors.len() {
+        return false;
+    }
+    mirrors.remove(index);
+    true
+}
+
+fn set_point(mirrors: &mut [io::Mirror], mirror: usize, handle: Handle, point: &[f64]) -> bool {
+    match mirrors.get_mut(mirror).and_then(|m| handle.get_mut(m)) {
+        Some(values) => {
+            *values = point.to_vec();
+            true
+        }
+        None => false,
+    }
+}
+
+fn replace<T: Clone>(items: &mut [T], index: usize, with: &T) -> bool {
+    match items.get_mut(index) {
+        Some(item) => {
+            *item = with.clone();
+            true
+        }
+        None => false,
+    }
+}
+
+// undo and redo stacks of edits. only the last `depth` edits are kept, older ones
+// fall off the bottom. serializable so it can be saved next to a scene and picked up again
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct History {
+    pub depth: usize,
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new(DEFAULT_DEPTH)
+    }
+}
+
+impl History {
+    pub fn new(depth: usize) -> History {
+        History {
+            depth,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    // remember an edit that was just made. a new edit makes the redo stack meaningless
+    pub fn push(&mut self, edit: Edit) {
+        self.redo.clear();
+        // pressing a key a few times on the same mirror is one change, not many
+        if let (
+            Some(Edit::Property { mirror, to, .. }),
+            Edit::Property {
+                mirror: new_mirror,
+                to: new_to,
+                ..
+            },
+        ) = (self.undo.back_mut(), &edit)
+        {
+            if mirror == new_mirror {
+                *to = new_to.clone();
+                return;
+            }
+        }
+        self.undo.push_back(edit);
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+
+    // take back the last edit. returns it if there was one that fit the scene. an edit
+    // that does not fit stays where it was
+    pub fn undo(&mut self, scene_file: &mut io::JSON) -> Option<&Edit> {
+        if !self.undo.back()?.revert(scene_file) {
+            return None;
+        }
+        let edit = self.undo.pop_back()?;
+        self.redo.push(edit);
+        self.redo.last()
+    }
+
+    // do the last undone edit again
+    pub fn redo(&mut self, scene_file: &mut io::JSON) -> Option<&Edit> {
+        if !self.redo.last()?.apply(scene_file) {
+            return None;
+        }
+        let edit = self.redo.pop()?;
+        self.undo.push_back(edit);
+        self.undo.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> io::JSON {
+        io::parse_json(
+            r#"{"version": 1, "coord_format": "pixels", "mirrors": [
+                {"start_pos": [0, 0], "end_pos": [10, 0], "absorption_factor": 0.0},
+                {"start_pos": [0, 5], "end_pos": [10, 5], "absorption_factor": 0.5}
+            ]}"#,
+        )
+        .unwrap()
+    }
+
+    // make an edit on the scene and record it, the way the editor does
+    fn edit(history: &mut History, scene_file: &mut io::JSON, edit: Edit) {
+        assert!(edit.apply(scene_file));
+        history.push(edit);
+    }
+
+    #[test]
+    fn undo_and_redo_walk_back_and_forth() {
+        let original = scene();
+        let mut scene_file = scene();
+        let mut history = History::default();
+
+        let new_mirror = io::Mirror::segment([1.0, 1.0], [2.0, 2.0], 0.0);
+        edit(
+            &mut history,
+            &mut scene_file,
+            Edit::Insert {
+                index: 1,
+                mirror: new_mirror,
+            },
+        );
+        edit(
+            &mut history,
+            &mut scene_file,
+            Edit::Move {
+                mirror: 0,
+                handle: Handle::End,
+                from: vec![10.0, 0.0],
+                to: vec![20.0, 0.0],
+            },
+        );
+        let deleted = scene_file.mirrors[2].clone();
+        edit(
+            &mut history,
+            &mut scene_file,
+            Edit::Delete {
+                index: 2,
+                mirror: deleted,
+            },
+        );
+        let edited = scene_file.clone();
+        assert_eq!(edited.mirrors.len(), 2);
+        assert_eq!(edited.mirrors[0].end_pos, vec![20.0, 0.0]);
+
+        while history.undo(&mut scene_file).is_some() {}
+        assert_eq!(scene_file.mirrors, original.mirrors);
+        while history.redo(&mut scene_file).is_some() {}
+        assert_eq!(scene_file.mirrors, edited.mirrors);
+
+        // a new edit after undoing drops what could have been redone
+        history.undo(&mut scene_file);
+        let from = scene_file.mirrors[0].clone();
+        let mut changed = from.clone();
+        changed.absorption_factor = 1.0;
+        edit(
+            &mut history,
+            &mut scene_file,
+            Edit::Property {
+                mirror: 0,
+                from: Box::new(from),
+                to: Box::new(changed),
+            },
+        );
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn property_changes_on_one_mirror_merge() {
+        let mut scene_file = scene();
+        let mut history = History::default();
+        for step in 1..=4 {
+            let from = scene_file.mirrors[1].clone();
+            let mut changed = from.clone();
+            changed.absorption_factor = 0.5 + step as f64 * 0.1;
+            edit(
+                &mut history,
+                &mut scene_file,
+                Edit::Property {
+                    mirror: 1,
+                    from: Box::new(from),
+                    to: Box::new(changed),
+                },
+            );
+        }
+        history.undo(&mut scene_file);
+        assert_eq!(scene_file.mirrors[1].absorption_factor, 0.5);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn depth_bounds_the_history_and_it_survives_json() {
+        let mut scene_file = scene();
+        let mut history = History::new(3);
+        for x in 1..=5 {
+            let from = scene_file.mirrors[0].start_pos.clone();
+            edit(
+                &mut history,
+                &mut scene_file,
+                Edit::Move {
+                    mirror: 0,
+                    handle: Handle::Start,
+                    from,
+                    to: vec![x as f64, 0.0],
+                },
+            );
+        }
+
+        let saved = serde_json::to_string(&history).unwrap();
+        let mut history: History = serde_json::from_str(&saved).unwrap();
+        let mut undone = 0;
+        while history.undo(&mut scene_file).is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, 3);
+        assert_eq!(scene_file.mirrors[0].start_pos, vec![2.0, 0.0]);
+    }
+
+    #[test]
+    fn edits_that_do_not_fit_stay_in_the_history() {
+        let mut scene_file = scene();
+        let mut history = History::default();
+        let mirror = io::Mirror::segment([1.0, 1.0], [2.0, 2.0], 0.0);
+        edit(
+            &mut history,
+            &mut scene_file,
+            Edit::Insert { index: 2, mirror },
+        );
+
+        // the inserted mirror is gone some other way, there is nothing to take back
+        let mut other = scene();
+        other.mirrors.truncate(1);
+        assert!(history.undo(&mut other).is_none());
+        assert!(history.can_undo());
+        assert!(history.undo(&mut scene_file).is_some());
+        assert_eq!(scene_file.mirrors.len(), 2);
+
+        // and the same the other way round
+        assert!(history.redo(&mut other).is_none());
+        assert!(history.can_redo());
+        assert!(history.redo(&mut scene_file).is_some());
+        assert_eq!(scene_file.mirrors.len(), 3);
+    }
+}
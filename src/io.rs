@@ -1,20 +1,1042 @@
+use crate::coords::{CoordFormat, UnknownCoordFormat};
+use crate::spectrum::{self, RefractiveIndex, Spectrum};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
 
-#[derive(Serialize, Deserialize)]
+// version of the scene format this build reads and writes. bump it whenever the
+// structs below change, and teach `migrate` how to get older files up to date
+pub const SCENE_VERSION: u32 = 2;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JSON {
-    pub(crate) coord_format: String,
-    pub(crate) mirrors: Vec<Mirror>,
+    // files from before versioning have no version key, they count as version 0
+    #[serde(default)]
+    pub version: u32,
+    pub coord_format: String,
+    pub mirrors: Vec<Mirror>,
+    // light sources. a scene without any gets lit from the cursor or the --source point
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<Light>,
+    // keys this build does not know about, kept so rewriting a scene never loses them
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Mirror {
-    pub(crate) start_pos: Vec<f64>,
-    pub(crate) end_pos: Vec<f64>,
-    pub(crate) absorption_factor: f64,
+    pub start_pos: Vec<f64>,
+    pub end_pos: Vec<f64>,
+    pub absorption_factor: f64,
+    // what happens to light hitting the segment. older scenes only have mirrors
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub surface: SurfaceKind,
+    // refractive index on the front side (where the normal points) and on the back side.
+    // only used by dielectric surfaces. a number, or a cauchy or sellmeier formula for
+    // glass that splits colors
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub refractive_index_front: RefractiveIndex,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub refractive_index_back: RefractiveIndex,
+    // geometry of the mirror. older scenes only have straight segments
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub shape: ShapeKind,
+    // arcs: a point the arc passes through between start_pos and end_pos
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub through_pos: Option<Vec<f64>>,
+    // parabolas: the vertex and the focus. start_pos and end_pos bound the mirror across the axis
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vertex: Option<Vec<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus: Option<Vec<f64>>,
+    // detectors: how many equal pieces the measured profile is split into
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bins: Option<usize>,
+    // diffuse and glossy surfaces: rays sent off per hit, each with an equal share of the
+    // energy. more than 1 multiplies the rays every bounce
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<usize>,
+    // glossy surfaces: exponent of the phong lobe. higher is closer to a mirror
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shininess: Option<f64>,
+    // splitters: the share of the light left after absorption that gets reflected, from 0
+    // to 1. the rest goes straight through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reflectance: Option<f64>,
+    // the side of the mirror that works, "left" or "right" of the way from start_pos to
+    // end_pos on screen. mirrors and detectors with a front ignore light from behind, glass
+    // takes refractive_index_front on it. left out, both sides reflect and glass has its
+    // front where the normal points
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub front: Option<Side>,
+    // what light reaching the back of a mirror with a front does: "absorb" or "pass"
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub back: BackFace,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Mirror {
+    // a plain straight mirror, everything else left at its default
+    pub fn segment(start_pos: [f64; 2], end_pos: [f64; 2], absorption_factor: f64) -> Mirror {
+        Mirror {
+            start_pos: start_pos.to_vec(),
+            end_pos: end_pos.to_vec(),
+            absorption_factor,
+            surface: SurfaceKind::default(),
+            refractive_index_front: RefractiveIndex::default(),
+            refractive_index_back: RefractiveIndex::default(),
+            shape: ShapeKind::default(),
+            through_pos: None,
+            vertex: None,
+            focus: None,
+            bins: None,
+            samples: None,
+            shininess: None,
+            reflectance: None,
+            front: None,
+            back: BackFace::default(),
+            extra: serde_json::Map::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: Vec<f64>,
+    // directional beams and line emitters: the other end of the line the light leaves from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_pos: Option<Vec<f64>>,
+    // where the light points, in degrees on screen. 0 is to the right and, as y points down,
+    // positive angles turn clockwise. angles are not stretched with the coordinates
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub angle: f64,
+    // spotlights: the full opening angle of the cone in degrees
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<f64>,
+    // rays this light sends out, the ray count of the trace if left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ray_count: Option<usize>,
+    // the wavelengths of the light: "white", {"monochromatic": 532} or {"band": [450, 500]}.
+    // lights without one send plain rays in the ray color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spectrum: Option<Spectrum>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LightKind {
+    // rays in every direction from one point
+    Point,
+    // parallel rays along `angle`, leaving from the line position to end_pos
+    Directional,
+    // a cone of rays around `angle`, `spread` degrees wide
+    Spotlight,
+    // rays in every direction from every point of the line position to end_pos
+    Line,
+    // a single ray along `angle`
+    Laser,
+}
+
+impl Light {
+    // the same light moved by an offset in scene coordinates
+    pub fn translated(&self, offset: [f64; 2]) -> Light {
+        let shift = |values: &Vec<f64>| -> Vec<f64> {
+            values
+                .iter()
+                .zip(offset.iter().chain(std::iter::repeat(&0.0)))
+                .map(|(value, offset)| value + offset)
+                .collect()
+        };
+        Light {
+            position: shift(&self.position),
+            end_pos: self.end_pos.as_ref().map(shift),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SurfaceKind {
+    #[default]
+    Mirror,
+    Dielectric,
+    // absorbs the light and measures it. has to be a straight segment
+    Detector,
+    // rough, sends light off in every direction like a matte wall
+    Diffuse,
+    // polished but not perfect, spreads light around the mirror direction
+    Glossy,
+    // half silvered, reflects part of the light and lets the rest through
+    Splitter,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    pub fn other(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackFace {
+    #[default]
+    Absorb,
+    Pass,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShapeKind {
+    #[default]
+    Segment,
+    Arc,
+    Parabola,
+}
+
+// defaults are left out when writing, so a scene reads back the way it was written
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+// everything that can go wrong turning a scene file into mirrors
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneError {
+    // the file could not be read at all
+    Io {
+        kind: std::io::ErrorKind,
+        message: String,
+    },
+    // the file is not valid json, or does not have the shape of a scene
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    UnknownCoordFormat(UnknownCoordFormat),
+    // a coordinate array that is not exactly two numbers
+    MalformedCoords {
+        mirror: usize,
+        field: &'static str,
+        len: usize,
+    },
+    // absorption_factor has to be a fraction of the light, 0 to 1
+    AbsorptionOutOfRange {
+        mirror: usize,
+        value: f64,
+    },
+    // a shape is missing one of the points it needs, e.g. an arc without through_pos
+    MissingField {
+        mirror: usize,
+        field: &'static str,
+    },
+    // a coordinate that is nan or infinite
+    NonFiniteCoords {
+        mirror: usize,
+        field: &'static str,
+    },
+    // a mirror without any extent, e.g. a segment that starts where it ends
+    DegenerateMirror {
+        mirror: usize,
+    },
+    // a refractive index that is not a positive number somewhere in the visible range
+    InvalidRefractiveIndex {
+        mirror: usize,
+        field: &'static str,
+    },
+    // a surface setting that makes no sense, e.g. a detector with 0 bins or a splitter
+    // reflecting more than all the light. `reason` says what the field should be
+    InvalidMirror {
+        mirror: usize,
+        field: &'static str,
+        reason: &'static str,
+    },
+    // a light source that cannot be built, e.g. a line emitter without end_pos
+    InvalidLight {
+        light: usize,
+        field: &'static str,
+        reason: &'static str,
+    },
+    // a file written by a newer build than this one
+    UnsupportedVersion {
+        found: u64,
+        supported: u32,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { message, .. } => write!(f, "could not read the scene: {}", message),
+            SceneError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            SceneError::UnknownCoordFormat(err) => write!(f, "{}", err),
+            SceneError::MalformedCoords { mirror, field, len } => write!(
+                f,
+                "mirror {}: \"{}\" needs exactly 2 numbers, found {}",
+                mirror, field, len
+            ),
+            SceneError::AbsorptionOutOfRange { mirror, value } => write!(
+                f,
+                "mirror {}: absorption_factor {} is outside of 0 to 1",
+                mirror, value
+            ),
+            SceneError::MissingField { mirror, field } => {
+                write!(f, "mirror {} is missing the field \"{}\"", mirror, field)
+            }
+            SceneError::NonFiniteCoords { mirror, field } => {
+                write!(
+                    f,
+                    "mirror {}: \"{}\" has to be finite numbers",
+                    mirror, field
+                )
+            }
+            SceneError::DegenerateMirror { mirror } => {
+                write!(f, "mirror {} has no extent, its points coincide", mirror)
+            }
+            SceneError::InvalidRefractiveIndex { mirror, field } => write!(
+                f,
+                "mirror {}: \"{}\" has to be a positive number for all visible light",
+                mirror, field
+            ),
+            SceneError::InvalidMirror {
+                mirror,
+                field,
+                reason,
+            } => write!(f, "mirror {}: \"{}\" {}", mirror, field, reason),
+            SceneError::InvalidLight {
+                light,
+                field,
+                reason,
+            } => write!(f, "light {}: \"{}\" {}", light, field, reason),
+            SceneError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "file version {} is newer than the supported version {}",
+                found, supported
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<UnknownCoordFormat> for SceneError {
+    fn from(err: UnknownCoordFormat) -> SceneError {
+        SceneError::UnknownCoordFormat(err)
+    }
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(err: std::io::Error) -> SceneError {
+        SceneError::Io {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> SceneError {
+        // serde puts the position at the end of its message as well, keep only the reason
+        let message = err.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(index) => message[..index].to_string(),
+            None => message,
+        };
+        SceneError::Parse {
+            line: err.line(),
+            column: err.column(),
+            message,
+        }
+    }
+}
+
+pub fn read_json(path: &str) -> Result<JSON, SceneError> {
+    let data = fs::read_to_string(path)?;
+    parse_json(&data)
+}
+
+// the version of a scene or session file, read on its own first so older files can be
+// migrated before the rest gets parsed. files from before versioning count as version 0
+pub(crate) fn file_version(data: &str) -> Result<u64, SceneError> {
+    #[derive(Deserialize)]
+    struct Versioned {
+        #[serde(default)]
+        version: u64,
+    }
+    Ok(serde_json::from_str::<Versioned>(data)?.version)
+}
+
+// parse a scene, migrating it to the current version if it is older
+pub fn parse_json(data: &str) -> Result<JSON, SceneError> {
+    let version = file_version(data)?;
+    let value: serde_json::Value = serde_json::from_str(data)?;
+    if version == SCENE_VERSION as u64 {
+        // parse the text again rather than the value, so errors keep their line and column
+        return Ok(serde_json::from_str::<JSON>(data)?);
+    }
+    if version > SCENE_VERSION as u64 {
+        return Err(SceneError::UnsupportedVersion {
+            found: version,
+            supported: SCENE_VERSION,
+        });
+    }
+    Ok(serde_json::from_value::<JSON>(migrate(value, version))?)
+}
+
+// bring an older scene up to SCENE_VERSION one version at a time
+fn migrate(mut value: serde_json::Value, mut version: u64) -> serde_json::Value {
+    while version < SCENE_VERSION as u64 {
+        if version == 0 {
+            // version 0 is everything from before the version key. the fields are the same,
+            // but points written from the old Vector2 model come as {"x": .., "y": ..}
+            if let Some(mirrors) = value
+                .get_mut("mirrors")
+                .and_then(serde_json::Value::as_array_mut)
+            {
+                for mirror in mirrors
+                    .iter_mut()
+                    .filter_map(serde_json::Value::as_object_mut)
+                {
+                    for point in mirror.values_mut() {
+                        if let (Some(x), Some(y)) = (point.get("x"), point.get("y")) {
+                            *point = serde_json::json!([x, y]);
+                        }
+                    }
+                }
+            }
+        }
+        // version 1 to 2 added lights, which are optional, so there is nothing to change
+        version += 1;
+    }
+    value["version"] = serde_json::json!(SCENE_VERSION);
+    value
+}
+
+// a coordinate array from the scene file as a point
+pub fn point(values: &[f64], mirror: usize, field: &'static str) -> Result<[f64; 2], SceneError> {
+    match values {
+        [x, y] => Ok([*x, *y]),
+        _ => Err(SceneError::MalformedCoords {
+            mirror,
+            field,
+            len: values.len(),
+        }),
+    }
 }
 
-pub(crate) fn read_json(path: &str) -> JSON {
-    let data = fs::read_to_string(path).expect("error reading json");
-    serde_json::from_str::<JSON>(data.as_str()).expect("error parsing json")
-}
\ No newline at end of file
+// check the whole scene and collect every problem, not just the first one
+pub fn validate(json_data: &JSON) -> Vec<SceneError> {
+    let mut problems: Vec<SceneError> = Vec::new();
+    if let Err(err) = json_data.coord_format.parse::<CoordFormat>() {
+        problems.push(err.into());
+    }
+    for (index, mirror) in json_data.mirrors.iter().enumerate() {
+        validate_mirror(index, mirror, &mut problems);
+    }
+    for (index, light) in json_data.lights.iter().enumerate() {
+        validate_light(index, light, &mut problems);
+    }
+    problems
+}
+
+// a coordinate array of a light as a point
+pub fn light_point(
+    values: &[f64],
+    light: usize,
+    field: &'static str,
+) -> Result<[f64; 2], SceneError> {
+    match values {
+        [x, y] if x.is_finite() && y.is_finite() => Ok([*x, *y]),
+        _ => Err(SceneError::InvalidLight {
+            light,
+            field,
+            reason: "needs exactly 2 finite numbers",
+        }),
+    }
+}
+
+fn validate_light(index: usize, light: &Light, problems: &mut Vec<SceneError>) {
+    let invalid = |field: &'static str, reason: &'static str| SceneError::InvalidLight {
+        light: index,
+        field,
+        reason,
+    };
+    let position = light_point(&light.position, index, "position")
+        .map_err(|err| problems.push(err))
+        .ok();
+    if !light.angle.is_finite() {
+        problems.push(invalid("angle", "has to be a finite number"));
+    }
+    if light.ray_count == Some(0) {
+        problems.push(invalid("ray_count", "has to be more than 0"));
+    }
+    match light.spectrum {
+        Some(Spectrum::Monochromatic(wavelength)) if wavelength.is_nan() || wavelength <= 0.0 => {
+            problems.push(invalid("spectrum", "needs a positive wavelength"))
+        }
+        Some(Spectrum::Band(min, max))
+            if min.is_nan() || max.is_nan() || min <= 0.0 || min > max =>
+        {
+            problems.push(invalid(
+                "spectrum",
+                "needs positive wavelengths, the shorter one first",
+            ))
+        }
+        _ => {}
+    }
+    match light.kind {
+        LightKind::Directional | LightKind::Line => match &light.end_pos {
+            None => problems.push(invalid("end_pos", "is missing")),
+            Some(values) => match light_point(values, index, "end_pos") {
+                Ok(end_pos) if Some(end_pos) == position => {
+                    problems.push(invalid("end_pos", "is the same point as position"))
+                }
+                Ok(_) => {}
+                Err(err) => problems.push(err),
+            },
+        },
+        LightKind::Spotlight => match light.spread {
+            None => problems.push(invalid("spread", "is missing")),
+            Some(spread) if !(spread > 0.0 && spread <= 360.0) => {
+                problems.push(invalid("spread", "has to be more than 0 and at most 360"))
+            }
+            Some(_) => {}
+        },
+        LightKind::Point | LightKind::Laser => {}
+    }
+}
+
+fn validate_mirror(index: usize, mirror: &Mirror, problems: &mut Vec<SceneError>) {
+    // a point has to be exactly two finite numbers
+    let mut checked_point = |values: &[f64], field: &'static str| match point(values, index, field)
+    {
+        Ok(p) if p.iter().all(|c| c.is_finite()) => Some(p),
+        Ok(_) => {
+            problems.push(SceneError::NonFiniteCoords {
+                mirror: index,
+                field,
+            });
+            None
+        }
+        Err(err) => {
+            problems.push(err);
+            None
+        }
+    };
+    let start_pos = checked_point(&mirror.start_pos, "start_pos");
+    let end_pos = checked_point(&mirror.end_pos, "end_pos");
+    let mut optional = |values: &Option<Vec<f64>>, field: &'static str| {
+        values
+            .as_ref()
+            .and_then(|values| checked_point(values, field))
+    };
+    let through_pos = optional(&mirror.through_pos, "through_pos");
+    let vertex = optional(&mirror.vertex, "vertex");
+    let focus = optional(&mirror.focus, "focus");
+
+    if !(0.0..=1.0).contains(&mirror.absorption_factor) {
+        problems.push(SceneError::AbsorptionOutOfRange {
+            mirror: index,
+            value: mirror.absorption_factor,
+        });
+    }
+    for (n, field) in [
+        (&mirror.refractive_index_front, "refractive_index_front"),
+        (&mirror.refractive_index_back, "refractive_index_back"),
+    ] {
+        // sellmeier formulas blow up near their poles, so check across the visible range
+        let steps = 37;
+        let valid = (0..=steps).all(|step| {
+            let wavelength = spectrum::VISIBLE_MIN
+                + (spectrum::VISIBLE_MAX - spectrum::VISIBLE_MIN) * step as f64 / steps as f64;
+            let value = n.at(wavelength);
+            value.is_finite() && value > 0.0
+        });
+        if !valid {
+            problems.push(SceneError::InvalidRefractiveIndex {
+                mirror: index,
+                field,
+            });
+        }
+    }
+
+    let invalid = |field, reason| SceneError::InvalidMirror {
+        mirror: index,
+        field,
+        reason,
+    };
+    if mirror.surface == SurfaceKind::Detector {
+        if mirror.shape != ShapeKind::Segment {
+            problems.push(invalid("shape", "has to be a segment for detectors"));
+        }
+        if mirror.bins == Some(0) {
+            problems.push(invalid("bins", "has to be more than 0"));
+        }
+    }
+
+    if mirror.samples == Some(0) {
+        problems.push(invalid("samples", "has to be more than 0"));
+    }
+    if mirror
+        .shininess
+        .is_some_and(|shininess| !shininess.is_finite() || shininess < 0.0)
+    {
+        problems.push(invalid(
+            "shininess",
+            "has to be a finite number of at least 0",
+        ));
+    }
+    if mirror
+        .reflectance
+        .is_some_and(|reflectance| !(0.0..=1.0).contains(&reflectance))
+    {
+        problems.push(invalid("reflectance", "has to be between 0 and 1"));
+    }
+
+    let missing = |values: &Option<Vec<f64>>, field: &'static str| {
+        values.is_none().then_some(SceneError::MissingField {
+            mirror: index,
+            field,
+        })
+    };
+    let degenerate = match mirror.shape {
+        ShapeKind::Segment => matches!((start_pos, end_pos), (Some(a), Some(b)) if a == b),
+        ShapeKind::Arc => {
+            problems.extend(missing(&mirror.through_pos, "through_pos"));
+            matches!((start_pos, through_pos, end_pos), (Some(a), Some(b), Some(c))
+                if a == b || b == c || a == c)
+        }
+        ShapeKind::Parabola => {
+            problems.extend(missing(&mirror.vertex, "vertex"));
+            problems.extend(missing(&mirror.focus, "focus"));
+            matches!((vertex, focus), (Some(a), Some(b)) if a == b)
+        }
+    };
+    if degenerate {
+        problems.push(SceneError::DegenerateMirror { mirror: index });
+    }
+}
+
+// write a scene, always as the current version
+pub fn write_json(path: &str, json_data: &JSON) -> std::io::Result<()> {
+    let mut json_data = json_data.clone();
+    json_data.version = SCENE_VERSION;
+    let data = serde_json::to_string_pretty(&json_data)?;
+    fs::write(path, data)
+}
+
+// re-express every point of a scene in another coordinate format. the points go through
+// window pixels for the given reference window size
+pub fn convert_coords(
+    json_data: &JSON,
+    to: CoordFormat,
+    window_dimensions: [f64; 2],
+) -> Result<JSON, SceneError> {
+    transform_scene(json_data, to, &[], window_dimensions)
+}
+
+// something to do to every point of a scene. positions are window pixels of the
+// reference window the scene gets converted for
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Transform {
+    // stretch away from origin, x and y separately
+    Scale { factor: [f64; 2], origin: [f64; 2] },
+    Translate { offset: [f64; 2] },
+    // turn around origin. y points down, so positive degrees turn clockwise on screen
+    Rotate { degrees: f64, origin: [f64; 2] },
+}
+
+impl Transform {
+    pub fn apply(&self, point: [f64; 2]) -> [f64; 2] {
+        match *self {
+            Transform::Scale { factor, origin } => [
+                origin[0] + (point[0] - origin[0]) * factor[0],
+                origin[1] + (point[1] - origin[1]) * factor[1],
+            ],
+            Transform::Translate { offset } => [point[0] + offset[0], point[1] + offset[1]],
+            Transform::Rotate { degrees, origin } => {
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                let [dx, dy] = [point[0] - origin[0], point[1] - origin[1]];
+                [
+                    origin[0] + dx * cos - dy * sin,
+                    origin[1] + dx * sin + dy * cos,
+                ]
+            }
+        }
+    }
+}
+
+// run the transforms in order over every point of the scene and write it in another
+// coordinate format. everything else in the scene, unknown keys included, is kept as is.
+// arcs and parabolas are defined by their points, so uneven scaling bends them into the
+// closest arc or parabola through the moved points rather than an ellipse
+pub fn transform_scene(
+    json_data: &JSON,
+    to: CoordFormat,
+    transforms: &[Transform],
+    window_dimensions: [f64; 2],
+) -> Result<JSON, SceneError> {
+    let from: CoordFormat = json_data.coord_format.parse()?;
+    // scaling by a negative factor mirrors the scene, which swaps left and right
+    let mirrored = transforms
+        .iter()
+        .filter(|transform| {
+            matches!(transform, Transform::Scale { factor, .. } if factor[0] * factor[1] < 0.0)
+        })
+        .count()
+        % 2
+        == 1;
+
+    let mut converted = json_data.clone();
+    converted.coord_format = to.as_str().to_string();
+    for (index, mirror) in converted.mirrors.iter_mut().enumerate() {
+        let convert = |values: &Vec<f64>, field: &'static str| {
+            let pixels = from.resolve(point(values, index, field)?, window_dimensions);
+            let moved = transforms
+                .iter()
+                .fold(pixels, |point, transform| transform.apply(point));
+            Ok::<Vec<f64>, SceneError>(to.unresolve(moved, window_dimensions).to_vec())
+        };
+        mirror.start_pos = convert(&mirror.start_pos, "start_pos")?;
+        mirror.end_pos = convert(&mirror.end_pos, "end_pos")?;
+        if let Some(through_pos) = &mirror.through_pos {
+            mirror.through_pos = Some(convert(through_pos, "through_pos")?);
+        }
+        if let Some(vertex) = &mirror.vertex {
+            mirror.vertex = Some(convert(vertex, "vertex")?);
+        }
+        if let Some(focus) = &mirror.focus {
+            mirror.focus = Some(convert(focus, "focus")?);
+        }
+        if mirrored {
+            match mirror.front {
+                // the same side of the mirror is on the other hand of start to end now
+                Some(front) => mirror.front = Some(front.other()),
+                // glass without a front has it where the normal points, which on a segment
+                // follows the way from start to end and so flips with the scene. curves
+                // get their normal from the center or focus, which mirroring keeps
+                None if mirror.shape == ShapeKind::Segment => std::mem::swap(
+                    &mut mirror.refractive_index_front,
+                    &mut mirror.refractive_index_back,
+                ),
+                None => {}
+            }
+        }
+    }
+    let apply_all = |pixels: [f64; 2]| {
+        transforms
+            .iter()
+            .fold(pixels, |point, transform| transform.apply(point))
+    };
+    for (index, light) in converted.lights.iter_mut().enumerate() {
+        let position = from.resolve(
+            light_point(&light.position, index, "position")?,
+            window_dimensions,
+        );
+        // angles are on screen, so they only change when the transforms turn or mirror
+        // the scene. follow a point one pixel along the light to see where it ends up
+        let (sin, cos) = light.angle.to_radians().sin_cos();
+        let ahead = apply_all([position[0] + cos, position[1] + sin]);
+        let moved = apply_all(position);
+        if !transforms.is_empty() {
+            light.angle = (ahead[1] - moved[1])
+                .atan2(ahead[0] - moved[0])
+                .to_degrees();
+        }
+        light.position = to.unresolve(moved, window_dimensions).to_vec();
+        if let Some(end_pos) = &light.end_pos {
+            let end_pos = from.resolve(light_point(end_pos, index, "end_pos")?, window_dimensions);
+            light.end_pos = Some(to.unresolve(apply_all(end_pos), window_dimensions).to_vec());
+        }
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+    use crate::tracer::Tracer;
+    use crate::physics;
+
+    fn parse(data: &str) -> Result<JSON, SceneError> {
+        parse_json(data)
+    }
+
+    #[test]
+    fn parse_errors_carry_their_position() {
+        let err = parse("{\n  \"coord_format\": \"pixels\",\n  \"mirrors\": [}\n").unwrap_err();
+        match err {
+            SceneError::Parse { line, column, .. } => assert_eq!((line, column), (3, 15)),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let err = read_json("assets/does_not_exist.json").unwrap_err();
+        assert!(matches!(
+            err,
+            SceneError::Io {
+                kind: std::io::ErrorKind::NotFound,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn short_coordinates_and_bad_absorption_are_rejected() {
+        let short = parse(
+            r#"{"coord_format": "pixels", "mirrors": [
+                {"start_pos": [0, 0], "end_pos": [5], "absorption_factor": 0.0}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            physics::mirrors_from_json(&short, [800.0, 800.0]).unwrap_err(),
+            SceneError::MalformedCoords {
+                mirror: 0,
+                field: "end_pos",
+                len: 1
+            }
+        );
+
+        let absorbing = parse(
+            r#"{"coord_format": "pixels", "mirrors": [
+                {"start_pos": [0, 0], "end_pos": [5, 5], "absorption_factor": 0.0},
+                {"start_pos": [0, 0], "end_pos": [5, 5], "absorption_factor": 1.5}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            physics::mirrors_from_json(&absorbing, [800.0, 800.0]).unwrap_err(),
+            SceneError::AbsorptionOutOfRange {
+                mirror: 1,
+                value: 1.5
+            }
+        );
+    }
+
+    #[test]
+    fn unversioned_scenes_are_migrated() {
+        let scene = parse(
+            r#"{"coord_format": "pixels", "mirrors": [
+                {"start_pos": {"x": 1, "y": 2}, "end_pos": [3, 4], "absorption_factor": 0.0}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(scene.version, SCENE_VERSION);
+        assert_eq!(scene.mirrors[0].start_pos, vec![1.0, 2.0]);
+
+        let bundled = read_json("assets/mirrors.json").unwrap();
+        assert_eq!(bundled.version, SCENE_VERSION);
+        assert!(validate(&bundled).is_empty());
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let err = parse(r#"{"version": 99, "coord_format": "pixels", "mirrors": []}"#).unwrap_err();
+        assert_eq!(
+            err,
+            SceneError::UnsupportedVersion {
+                found: 99,
+                supported: SCENE_VERSION
+            }
+        );
+    }
+
+    #[test]
+    fn versions_that_are_not_whole_numbers_are_refused() {
+        for version in ["1.5", "-1", "\"2\"", "null"] {
+            let data = format!(
+                r#"{{"version": {}, "coord_format": "pixels", "mirrors": []}}"#,
+                version
+            );
+            assert!(
+                matches!(parse(&data), Err(SceneError::Parse { line: 1, .. })),
+                "{}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn validation_reports_every_problem() {
+        let scene = parse(
+            r#"{"version": 1, "coord_format": "inches", "mirrors": [
+                {"start_pos": [1, 1], "end_pos": [1, 1], "absorption_factor": 0.0},
+                {"start_pos": [0, 0, 0], "end_pos": [5, 5], "absorption_factor": -0.5},
+                {"start_pos": [0, 0], "end_pos": [5, 5], "absorption_factor": 0.0, "shape": "arc"},
+                {"start_pos": [0, 0], "end_pos": [5, 5], "absorption_factor": 0.0,
+                 "surface": "glossy", "samples": 0, "shininess": -1},
+                {"start_pos": [0, 0], "end_pos": [5, 5], "absorption_factor": 0.0,
+                 "surface": "splitter", "reflectance": 1.5}
+            ]}"#,
+        )
+        .unwrap();
+        let problems = validate(&scene);
+        assert_eq!(problems.len(), 8, "{:?}", problems);
+        assert!(matches!(problems[0], SceneError::UnknownCoordFormat(_)));
+        assert_eq!(problems[1], SceneError::DegenerateMirror { mirror: 0 });
+        assert!(matches!(
+            problems[2],
+            SceneError::MalformedCoords { mirror: 1, .. }
+        ));
+        assert!(matches!(
+            problems[3],
+            SceneError::AbsorptionOutOfRange { mirror: 1, .. }
+        ));
+        assert_eq!(
+            problems[4],
+            SceneError::MissingField {
+                mirror: 2,
+                field: "through_pos"
+            }
+        );
+        assert!(matches!(
+            problems[5..],
+            [
+                SceneError::InvalidMirror {
+                    mirror: 3,
+                    field: "samples",
+                    ..
+                },
+                SceneError::InvalidMirror {
+                    mirror: 3,
+                    field: "shininess",
+                    ..
+                },
+                SceneError::InvalidMirror {
+                    mirror: 4,
+                    field: "reflectance",
+                    ..
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn conversion_round_trips_every_field() {
+        let original = parse(
+            r#"{"version": 1, "coord_format": "pixels", "author": "bench", "mirrors": [
+                {"start_pos": [108, 192], "end_pos": [540, 960], "absorption_factor": 0.25,
+                 "surface": "dielectric", "refractive_index_front": 1.5, "label": "prism"},
+                {"start_pos": [0, 0], "end_pos": [1080, 0], "absorption_factor": 1.0,
+                 "shape": "arc", "through_pos": [540, 300]}
+            ]}"#,
+        )
+        .unwrap();
+        let dimensions = [1080.0, 1920.0];
+        let fractions = convert_coords(&original, CoordFormat::Fractions, dimensions).unwrap();
+        assert_eq!(fractions.coord_format, "fractions");
+        assert_eq!(fractions.mirrors[0].start_pos, vec![0.1, 0.1]);
+        let back = convert_coords(&fractions, CoordFormat::Pixels, dimensions).unwrap();
+
+        let written = serde_json::to_value(&back).unwrap();
+        assert_eq!(written, serde_json::to_value(&original).unwrap());
+        assert_eq!(written["author"], "bench");
+        assert_eq!(written["mirrors"][0]["label"], "prism");
+        assert_eq!(written["mirrors"][0]["refractive_index_front"], 1.5);
+        assert_eq!(
+            written["mirrors"][1]["through_pos"],
+            serde_json::json!([540.0, 300.0])
+        );
+        // defaults stay out of the file
+        assert!(written["mirrors"][1].get("surface").is_none());
+    }
+
+    #[test]
+    fn transforms_apply_in_order() {
+        let scene = parse(
+            r#"{"version": 1, "coord_format": "pixels", "mirrors": [
+                {"start_pos": [110, 100], "end_pos": [120, 100], "absorption_factor": 0.0}
+            ]}"#,
+        )
+        .unwrap();
+        let origin = [100.0, 100.0];
+        let transforms = [
+            Transform::Scale {
+                factor: [2.0, 2.0],
+                origin,
+            },
+            Transform::Rotate {
+                degrees: 90.0,
+                origin,
+            },
+            Transform::Translate { offset: [5.0, 0.0] },
+        ];
+        let moved =
+            transform_scene(&scene, CoordFormat::Pixels, &transforms, [800.0, 800.0]).unwrap();
+        let close = |values: &[f64], expected: [f64; 2]| {
+            assert!((values[0] - expected[0]).abs() < 1e-9, "{:?}", values);
+            assert!((values[1] - expected[1]).abs() < 1e-9, "{:?}", values);
+        };
+        close(&moved.mirrors[0].start_pos, [105.0, 120.0]);
+        close(&moved.mirrors[0].end_pos, [105.0, 140.0]);
+    }
+
+    #[test]
+    fn mirroring_swaps_the_front() {
+        let scene = parse(
+            r#"{"version": 2, "coord_format": "pixels", "mirrors": [
+                {"start_pos": [0, 0], "end_pos": [10, 0], "absorption_factor": 0.0,
+                 "front": "left", "back": "pass"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(scene.mirrors[0].front, Some(Side::Left));
+        assert_eq!(scene.mirrors[0].back, BackFace::Pass);
+
+        let flip = Transform::Scale {
+            factor: [-1.0, 1.0],
+            origin: [0.0, 0.0],
+        };
+        let flipped = transform_scene(&scene, CoordFormat::Pixels, &[flip], [800.0, 800.0]);
+        assert_eq!(flipped.unwrap().mirrors[0].front, Some(Side::Right));
+        // turning keeps the sides
+        let turn = Transform::Rotate {
+            degrees: 180.0,
+            origin: [0.0, 0.0],
+        };
+        let turned = transform_scene(&scene, CoordFormat::Pixels, &[turn], [800.0, 800.0]);
+        assert_eq!(turned.unwrap().mirrors[0].front, Some(Side::Left));
+    }
+
+    #[test]
+    fn mirrored_glass_keeps_its_sides() {
+        let window = [800.0, 800.0];
+        let scene = read_json("assets/prism.json").unwrap();
+        let detected = |scene: &JSON| {
+            let scene = Scene::from_json(scene, window).unwrap();
+            let rays = scene.emit(10, [400.0, 400.0]);
+            Tracer::new().trace(&scene, rays).detector_hits.len()
+        };
+        assert_eq!(detected(&scene), 3000);
+
+        let flip = Transform::Scale {
+            factor: [-1.0, 1.0],
+            origin: [400.0, 400.0],
+        };
+        let flipped = transform_scene(&scene, CoordFormat::AspectFractions, &[flip], window);
+        assert_eq!(detected(&flipped.unwrap()), 3000);
+    }
+}
@@ -0,0 +1,22 @@
+// headless ray tracing of light bouncing between mirrors. the piston app in main.rs is
+// one frontend, tests, scripts and other tools can drive traces through the same api
+pub mod bvh;
+pub mod coords;
+pub mod detector;
+pub mod editor;
+pub mod history;
+pub mod io;
+pub mod light;
+pub mod paths;
+pub mod physics;
+pub mod raster;
+pub mod scene;
+pub mod session;
+pub mod spectrum;
+pub mod tracer;
+pub mod worker;
+
+pub use light::LightSource;
+pub use physics::{Mirror, Ray, Segment, Shape, Surface};
+pub use scene::Scene;
+pub use tracer::{Trace, Tracer};
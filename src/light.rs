@@ -0,0 +1,308 @@
+use crate::coords::CoordFormat;
+use crate::io;
+use crate::physics::{self, ColorComponent, Ray};
+use crate::spectrum::{self, Spectrum};
+
+// color of the rays of sources without a spectrum. yellow, like generate_rays
+const LIGHT_COLOR: [ColorComponent; 4] = [1.0, 1.0, 0.0, 1.0];
+
+// a light source resolved to window pixels, ready to send out rays
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LightSource {
+    pub kind: LightKind,
+    // rays to send out, None uses the count the trace was started with
+    pub ray_count: Option<usize>,
+    // wavelengths of the rays, None sends plain rays without a wavelength
+    pub spectrum: Option<Spectrum>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightKind {
+    // evenly spaced rays in every direction
+    Point {
+        position: [f64; 2],
+    },
+    // parallel rays along direction, leaving from evenly spaced points between start and end
+    Directional {
+        start: [f64; 2],
+        end: [f64; 2],
+        direction: [f64; 2],
+    },
+    // evenly spaced rays inside a cone `spread` radians wide around direction
+    Spotlight {
+        position: [f64; 2],
+        direction: [f64; 2],
+        spread: f64,
+    },
+    // rays from evenly spaced points between start and end, every one in another direction
+    Line {
+        start: [f64; 2],
+        end: [f64; 2],
+    },
+    // one ray
+    Laser {
+        position: [f64; 2],
+        direction: [f64; 2],
+    },
+}
+
+impl LightSource {
+    pub fn point(position: [f64; 2], ray_count: usize) -> LightSource {
+        LightSource {
+            kind: LightKind::Point { position },
+            ray_count: Some(ray_count),
+            spectrum: None,
+        }
+    }
+
+    // where the light sits, used to draw it and to grab it in edit mode
+    pub fn position(&self) -> [f64; 2] {
+        match self.kind {
+            LightKind::Point { position }
+            | LightKind::Spotlight { position, .. }
+            | LightKind::Laser { position, .. } => position,
+            LightKind::Directional { start, .. } | LightKind::Line { start, .. } => start,
+        }
+    }
+
+    // the rays this light starts a trace with
+    pub fn rays(&self, default_ray_count: usize) -> Vec<Ray> {
+        let count = self.ray_count.unwrap_or(default_ray_count);
+        // spot in the middle of the n-th of `count` equal pieces, so ends are never doubled
+        let fraction = |n: usize| (n as f64 + 0.5) / count as f64;
+        let along = |start: [f64; 2], end: [f64; 2], t: f64| {
+            [
+                start[0] + t * (end[0] - start[0]),
+                start[1] + t * (end[1] - start[1]),
+            ]
+        };
+        let ray = |start_pos: [f64; 2], vector: [f64; 2]| Ray {
+            start_pos,
+            vector,
+            color: LIGHT_COLOR,
+            energy: 1.0,
+            wavelength: None,
+            bounces: 0,
+            id: 0,
+        };
+
+        let mut rays: Vec<Ray> = match self.kind {
+            LightKind::Point { position } => physics::generate_rays(count as f64, position),
+            LightKind::Directional {
+                start,
+                end,
+                direction,
+            } => (0..count)
+                .map(|n| ray(along(start, end, fraction(n)), direction))
+                .collect(),
+            LightKind::Spotlight {
+                position,
+                direction,
+                spread,
+            } => {
+                let center = direction[1].atan2(direction[0]);
+                (0..count)
+                    .map(|n| {
+                        let angle = center + spread * (fraction(n) - 0.5);
+                        ray(position, [angle.cos(), angle.sin()])
+                    })
+                    .collect()
+            }
+            LightKind::Line { start, end } => {
+                // directions follow the golden angle, so every stretch of the line sends
+                // light everywhere instead of each point getting one fixed direction
+                let golden = (5f64.sqrt() - 1.0) / 2.0;
+                (0..count)
+                    .map(|n| {
+                        let angle = std::f64::consts::TAU * (n as f64 * golden).fract();
+                        ray(along(start, end, fraction(n)), [angle.cos(), angle.sin()])
+                    })
+                    .collect()
+            }
+            LightKind::Laser {
+                position,
+                direction,
+            } => vec![ray(position, direction)],
+        };
+
+        if let Some(light_spectrum) = self.spectrum {
+            let total = rays.len();
+            for (n, ray) in rays.iter_mut().enumerate() {
+                let wavelength = light_spectrum.sample(n, total);
+                let [r, g, b] = spectrum::wavelength_to_rgb(wavelength);
+                ray.wavelength = Some(wavelength);
+                ray.color = [r, g, b, 1.0];
+            }
+        }
+        rays
+    }
+}
+
+// the starting rays of every light together, in the order of the lights.
+// the rays are numbered through, so every one has its own id
+pub fn emit(lights: &[LightSource], default_ray_count: usize) -> Vec<Ray> {
+    let mut rays: Vec<Ray> = lights
+        .iter()
+        .flat_map(|light| light.rays(default_ray_count))
+        .collect();
+    for (id, ray) in rays.iter_mut().enumerate() {
+        ray.id = id;
+    }
+    rays
+}
+
+// resolve the lights of a scene file against the window size
+pub fn lights_from_json(
+    json_data: &io::JSON,
+    window_dimensions: [f64; 2],
+) -> Result<Vec<LightSource>, io::SceneError> {
+    let coord_format: CoordFormat = json_data.coord_format.parse()?;
+    let mut lights = Vec::new();
+    for (index, light) in json_data.lights.iter().enumerate() {
+        let resolve = |values: &[f64], field: &'static str| {
+            Ok::<[f64; 2], io::SceneError>(
+                coord_format.resolve(io::light_point(values, index, field)?, window_dimensions),
+            )
+        };
+        let missing = |field| io::SceneError::InvalidLight {
+            light: index,
+            field,
+            reason: "is missing",
+        };
+        let end_pos = || match &light.end_pos {
+            Some(values) => resolve(values, "end_pos"),
+            None => Err(missing("end_pos")),
+        };
+        let position = resolve(&light.position, "position")?;
+        let (sin, cos) = light.angle.to_radians().sin_cos();
+        let direction = [cos, sin];
+
+        let kind = match light.kind {
+            io::LightKind::Point => LightKind::Point { position },
+            io::LightKind::Directional => LightKind::Directional {
+                start: position,
+                end: end_pos()?,
+                direction,
+            },
+            io::LightKind::Spotlight => LightKind::Spotlight {
+                position,
+                direction,
+                spread: light.spread.ok_or(missing("spread"))?.to_radians(),
+            },
+            io::LightKind::Line => LightKind::Line {
+                start: position,
+                end: end_pos()?,
+            },
+            io::LightKind::Laser => LightKind::Laser {
+                position,
+                direction,
+            },
+        };
+        lights.push(LightSource {
+            kind,
+            ray_count: light.ray_count,
+            spectrum: light.spectrum,
+        });
+    }
+    Ok(lights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: [f64; 2], b: [f64; 2]) -> bool {
+        (a[0] - b[0]).abs() < 1e-9 && (a[1] - b[1]).abs() < 1e-9
+    }
+
+    #[test]
+    fn every_kind_sends_the_rays_it_describes() {
+        let scene = io::parse_json(
+            r#"{"version": 2, "coord_format": "fractions", "mirrors": [], "lights": [
+                {"kind": "point", "position": [0.5, 0.5]},
+                {"kind": "directional", "position": [0.0, 0.25], "end_pos": [0.0, 0.75],
+                 "angle": 0, "ray_count": 4},
+                {"kind": "spotlight", "position": [0.5, 0.0], "angle": 90, "spread": 60,
+                 "ray_count": 3},
+                {"kind": "line", "position": [0.25, 1.0], "end_pos": [0.75, 1.0], "ray_count": 8},
+                {"kind": "laser", "position": [1.0, 0.5], "angle": 180}
+            ]}"#,
+        )
+        .unwrap();
+        let lights = lights_from_json(&scene, [800.0, 400.0]).unwrap();
+
+        let point = lights[0].rays(10);
+        assert_eq!(point.len(), 10);
+        assert!(point.iter().all(|ray| ray.start_pos == [400.0, 200.0]));
+
+        let beam = lights[1].rays(10);
+        let starts: Vec<f64> = beam.iter().map(|ray| ray.start_pos[1]).collect();
+        assert_eq!(starts, vec![125.0, 175.0, 225.0, 275.0]);
+        assert!(beam.iter().all(|ray| close(ray.vector, [1.0, 0.0])));
+
+        // a 60 degree cone pointing down, three rays at -20, 0 and 20 degrees off center
+        let cone = lights[2].rays(10);
+        assert_eq!(cone.len(), 3);
+        assert!(close(cone[1].vector, [0.0, 1.0]));
+        let off_center = cone[0].vector[0].atan2(cone[0].vector[1]).to_degrees();
+        assert!((off_center.abs() - 20.0).abs() < 1e-9, "{}", off_center);
+
+        let line = lights[3].rays(10);
+        assert_eq!(line.len(), 8);
+        assert!(line
+            .iter()
+            .all(|ray| ray.start_pos[1] == 400.0 && (200.0..=600.0).contains(&ray.start_pos[0])));
+
+        let laser = lights[4].rays(10);
+        assert_eq!(laser.len(), 1);
+        assert!(close(laser[0].vector, [-1.0, 0.0]));
+
+        assert_eq!(emit(&lights, 10).len(), 10 + 4 + 3 + 8 + 1);
+    }
+
+    #[test]
+    fn broken_lights_are_all_reported() {
+        let scene = io::parse_json(
+            r#"{"version": 2, "coord_format": "pixels", "mirrors": [], "lights": [
+                {"kind": "line", "position": [1, 1]},
+                {"kind": "spotlight", "position": [1], "spread": 400},
+                {"kind": "directional", "position": [1, 1], "end_pos": [1, 1], "ray_count": 0}
+            ]}"#,
+        )
+        .unwrap();
+        let problems = io::validate(&scene);
+        let fields: Vec<(usize, &str)> = problems
+            .iter()
+            .map(|problem| match problem {
+                io::SceneError::InvalidLight { light, field, .. } => (*light, *field),
+                other => panic!("expected a light problem, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                (0, "end_pos"),
+                (1, "position"),
+                (1, "spread"),
+                (2, "ray_count"),
+                (2, "end_pos")
+            ]
+        );
+
+        // building the lights without validating first fails the same way
+        let spotlight = io::parse_json(
+            r#"{"version": 2, "coord_format": "pixels", "mirrors": [], "lights": [
+                {"kind": "spotlight", "position": [1, 1]}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            lights_from_json(&spotlight, [100.0, 100.0]),
+            Err(io::SceneError::InvalidLight {
+                light: 0,
+                field: "spread",
+                ..
+            })
+        ));
+    }
+}
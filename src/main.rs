@@ -1,46 +1,164 @@
-mod io;
-mod physics;
+mod cli;
 
+use clap::Parser;
+use cli::{Cli, Command, SceneCommand};
 use piston::EventLoop;
+use piston2d_testing::coords::CoordFormat;
+use piston2d_testing::detector::{self, DetectorHit};
+use piston2d_testing::editor::{self, Change, Editor};
+use piston2d_testing::light::LightKind;
+use piston2d_testing::worker::TraceWorker;
+use piston2d_testing::{io, paths, physics, raster, session, Scene, Tracer};
+use piston_window::texture::{CreateTexture, Format, UpdateTexture};
 use piston_window::*;
 // use rand::prelude::*;
-use std::process::exit;
 use piston_window::types::ColorComponent;
+use std::process::exit;
 
 fn main() {
+    let cli = Cli::parse();
+    let code = match cli.command {
+        None => view(cli.view),
+        Some(Command::View(args)) => view(args),
+        Some(Command::Render(args)) => render(args),
+        Some(Command::Trace(args)) => trace_to_file(args),
+        Some(Command::Measure(args)) => measure(args),
+        Some(Command::Paths(args)) => paths_to_file(args),
+        Some(Command::Scene(args)) => scene_command(args.command),
+    };
+    exit(code);
+}
+
+const WINDOW_TITLE: &str = "piston2d";
+
+// build the tracer from the shared command line settings
+fn tracer_from_args(args: &cli::TraceArgs) -> Tracer {
+    physics::set_thread_count(args.threads);
+    Tracer {
+        max_bounces: args.max_reflections,
+        energy_threshold: args.energy_threshold,
+        // one thread traces serially, without handing the rays to the thread pool
+        parallel: args.threads != 1,
+        record_paths: false,
+        seed: args.seed,
+        max_rays: args.max_rays,
+    }
+}
+
+// where Ctrl+Shift+S saves the session if no --session was given
+const DEFAULT_SESSION_PATH: &str = "session.json";
+
+// the interactive piston window
+fn view(args: cli::ViewArgs) -> i32 {
+    // a saved session replaces everything that would come from the command line
+    let session_path = args
+        .session
+        .clone()
+        .unwrap_or(DEFAULT_SESSION_PATH.to_string());
+    let session = match &args.session {
+        Some(path) if std::path::Path::new(path).exists() => match session::read_session(path) {
+            Ok(session) => Some(session),
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                return 1;
+            }
+        },
+        _ => None,
+    };
+    // where M writes the detector measurements
+    let measure_output = args.measure_output.clone();
+    let window_size = match &session {
+        Some(session) => session.view.window_size,
+        None => [args.width as f64, args.height as f64],
+    };
+
     // configure piston window
     let opengl = OpenGL::V3_2;
-    let mut window: PistonWindow = WindowSettings::new("piston2d", [800; 2])
+    let mut window: PistonWindow = WindowSettings::new(WINDOW_TITLE, window_size)
         .exit_on_esc(true)
         .graphics_api(opengl)
         .build()
         .unwrap();
-    window.set_lazy(true);
+    // the trace runs on its own thread, so frames keep coming to pick up its batches
+    window.set_lazy(false);
+    window.set_max_fps(60);
 
     // setup random float provider
     // let mut rng = rand::rng();
 
     // configure "global" variables (cursor pos, etc)
     let mut mouse_pos = [window.size().width, window.size().height];
+    // where the light source sits. space moves it to the cursor
+    let mut source_pos = session.as_ref().map_or(mouse_pos, |s| s.source_pos);
 
-    // generate mirrors. these only need to generate once, so are outside the while loop
+    // load the scene once. the mirrors are resolved against the window size,
+    // so they only need to regenerate when the window gets resized
     // let mirrors = physics::generate_mirrors(10);
-    let mirrors = physics::generate_mirrors_json("assets/mirrors.json");
-    // this counts the computed reflections (to be able to fix limits)
-    let mut reflection_counter = 0;
-    const MAX_REFLECTIONS: i32 = 50;
-
+    let scene_path = session
+        .as_ref()
+        .map_or(args.trace.scene.clone(), |s| s.scene_path.clone());
+    let loaded = match &session {
+        Some(session) => Scene::from_json(&session.scene, window_size)
+            .map(|scene| (session.scene.clone(), scene)),
+        None => load_scene(&scene_path, window_size),
+    };
+    let (mut scene_file, mut scene) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{}: {}", scene_path, err);
+            return 1;
+        }
+    };
     // this lets us reset the sim
     let mut reset = true;
 
     // this lets us set the amount of rays
-    const RAY_COUNT: f64 = 5000f64;
+    let mut ray_count = args.trace.rays as f64;
 
-    // keep track of all objects to draw
+    let mut tracer = tracer_from_args(&args.trace);
 
-    let mut rays: Vec<physics::Ray> = physics::generate_rays(RAY_COUNT, mouse_pos);
+    let mut ray_color: [ColorComponent; 4] = [
+        args.ray_color[0],
+        args.ray_color[1],
+        args.ray_color[2],
+        args.ray_color[3],
+    ];
 
-    let mut clear_once = true;
+    // traces generation after generation in the background, the loop below only draws
+    // what it sends over
+    let mut worker = TraceWorker::spawn();
+
+    // the light of every traced segment piles up here, the window shows it tone mapped
+    let mut tone_map = args.tone.tone_map;
+    let mut exposure = args.tone.exposure;
+    let mut accumulator =
+        raster::Accumulator::new(window_size[0] as usize, window_size[1] as usize);
+    let mut texture_context = window.create_texture_context();
+    let mut texture: Option<G2dTexture> = None;
+    // whether the texture is behind the accumulator
+    let mut retone = true;
+    // everything the detectors got since the last reset
+    let mut detector_hits: Vec<DetectorHit> = Vec::new();
+
+    // edit mode: the mouse places and drags mirrors instead of just pointing
+    let mut editing = false;
+    let mut editor = Editor::new(args.history_depth);
+    let mut ctrl_held = false;
+    let mut shift_held = false;
+
+    if let Some(session) = session {
+        ray_count = session.ray_count as f64;
+        tracer.max_bounces = session.max_reflections;
+        tracer.energy_threshold = session.energy_threshold;
+        tracer.seed = session.seed;
+        tracer.max_rays = session.max_rays;
+        ray_color = session.ray_color;
+        editing = session.view.editing;
+        tone_map = session.view.tone_map;
+        exposure = session.view.exposure;
+        editor.history = session.history;
+        window.set_title(window_title(editing));
+    }
 
     // main draw loop, call draw() here
     while let Some(e) = window.next() {
@@ -49,54 +167,251 @@ fn main() {
             use piston_window::Button::Keyboard;
 
             if *args == Keyboard(Key::Escape) {
-                exit(0);
+                return 0;
             }
             if *args == Keyboard(Key::Space) {
+                source_pos = mouse_pos;
                 reset = true;
             }
+            if *args == Keyboard(Key::LCtrl) || *args == Keyboard(Key::RCtrl) {
+                ctrl_held = true;
+            }
+            if *args == Keyboard(Key::LShift) || *args == Keyboard(Key::RShift) {
+                shift_held = true;
+            }
+            // reload the scene file from disk. a broken file keeps the old scene around
+            if *args == Keyboard(Key::R) {
+                match load_scene(&scene_path, [window.size().width, window.size().height]) {
+                    Ok((new_file, new_scene)) => {
+                        scene_file = new_file;
+                        scene = new_scene;
+                        // the old edits were made to a different file
+                        editor.history.clear();
+                        editor.clamp_selection(&scene_file);
+                        window.set_title(window_title(editing));
+                        reset = true;
+                    }
+                    Err(err) => show_scene_error(&mut window, &scene_path, &err),
+                }
+            }
+            if *args == Keyboard(Key::T) {
+                tone_map = tone_map.next();
+                println!("tone map: {}", tone_map);
+                retone = true;
+            }
+            if *args == Keyboard(Key::Equals) || *args == Keyboard(Key::NumPadPlus) {
+                exposure *= 2.0;
+                println!("exposure: {}", exposure);
+                retone = true;
+            }
+            if *args == Keyboard(Key::Minus) || *args == Keyboard(Key::NumPadMinus) {
+                exposure /= 2.0;
+                println!("exposure: {}", exposure);
+                retone = true;
+            }
+            if *args == Keyboard(Key::M) {
+                let path = &measure_output;
+                let format = if path.ends_with(".json") {
+                    cli::TraceFormat::Json
+                } else {
+                    cli::TraceFormat::Csv
+                };
+                let written = measurements(&scene, &detector_hits, format, false)
+                    .map_err(std::io::Error::from)
+                    .and_then(|data| std::fs::write(path, data));
+                match written {
+                    Ok(()) => println!("wrote {} detector hits to {}", detector_hits.len(), path),
+                    Err(err) => eprintln!("error writing {}: {}", path, err),
+                }
+            }
+            if *args == Keyboard(Key::P) {
+                if worker.is_paused() {
+                    worker.resume();
+                    println!("resumed");
+                } else {
+                    worker.pause();
+                    println!("paused, N traces one more generation");
+                }
+            }
+            if *args == Keyboard(Key::N) && worker.is_paused() {
+                worker.step();
+            }
+            if *args == Keyboard(Key::E) {
+                editing = !editing;
+                editor.selected = None;
+                editor.drag = None;
+                window.set_title(window_title(editing));
+                reset = true;
+            }
+            if *args == Keyboard(Key::S) && ctrl_held && shift_held {
+                let session = session::Session {
+                    version: session::SESSION_VERSION,
+                    scene_path: scene_path.clone(),
+                    scene: scene_file.clone(),
+                    source_pos,
+                    ray_count: ray_count as usize,
+                    max_reflections: tracer.max_bounces,
+                    energy_threshold: tracer.energy_threshold,
+                    seed: tracer.seed,
+                    max_rays: tracer.max_rays,
+                    ray_color,
+                    view: session::ViewSettings {
+                        window_size: [window.size().width, window.size().height],
+                        editing,
+                        tone_map,
+                        exposure,
+                    },
+                    history: editor.history.clone(),
+                };
+                match session::write_session(&session_path, &session) {
+                    Ok(()) => println!("saved the session to {}", session_path),
+                    Err(err) => eprintln!("error writing {}: {}", session_path, err),
+                }
+            } else if *args == Keyboard(Key::S) && ctrl_held {
+                save_scene(&mut window, &scene_path, &scene_file);
+            }
+            if *args == Keyboard(Key::Z) && ctrl_held {
+                let change = if shift_held {
+                    editor.redo(&mut scene_file)
+                } else {
+                    editor.undo(&mut scene_file)
+                };
+                reset |= apply_edit(
+                    &mut window,
+                    &scene_path,
+                    &scene_file,
+                    &mut scene,
+                    Ok(change),
+                );
+            }
+
+            if editing {
+                let window_dimensions = [window.size().width, window.size().height];
+                let change = if *args == Button::Mouse(MouseButton::Left) {
+                    editor.press(&scene_file, &scene, mouse_pos, window_dimensions)
+                } else if *args == Keyboard(Key::Delete) || *args == Keyboard(Key::Backspace) {
+                    Ok(editor.delete_selected(&mut scene_file))
+                } else if *args == Keyboard(Key::Up) {
+                    Ok(editor.adjust_absorption(&mut scene_file, editor::ABSORPTION_STEP))
+                } else if *args == Keyboard(Key::Down) {
+                    Ok(editor.adjust_absorption(&mut scene_file, -editor::ABSORPTION_STEP))
+                } else if *args == Keyboard(Key::F) {
+                    Ok(editor.cycle_front(&mut scene_file))
+                } else {
+                    Ok(Change::Nothing)
+                };
+                reset |= apply_edit(&mut window, &scene_path, &scene_file, &mut scene, change);
+            }
+        }
+
+        if let Some(ref args) = e.release_args() {
+            use piston_window::Button::Keyboard;
+
+            if *args == Keyboard(Key::LCtrl) || *args == Keyboard(Key::RCtrl) {
+                ctrl_held = false;
+            }
+            if *args == Keyboard(Key::LShift) || *args == Keyboard(Key::RShift) {
+                shift_held = false;
+            }
+            if editing && *args == Button::Mouse(MouseButton::Left) {
+                let window_dimensions = [window.size().width, window.size().height];
+                let change = editor.release(&mut scene_file, window_dimensions);
+                reset |= apply_edit(&mut window, &scene_path, &scene_file, &mut scene, change);
+            }
         }
 
         // process mouse events
         if let Some(ref args) = e.mouse_cursor_args() {
             // update mouse pos every frame
             mouse_pos = *args;
+            if editing {
+                let window_dimensions = [window.size().width, window.size().height];
+                let change = editor.motion(&mut scene_file, mouse_pos, window_dimensions);
+                reset |= apply_edit(&mut window, &scene_path, &scene_file, &mut scene, change);
+            }
         }
 
-        // render
-        window.draw_2d(&e, |c: Context, g: &mut G2d, _| {
-            // let white = [1.0; 4];
-            let black = [0.0, 0.0, 0.0, 1.0];
-            // check for reset, if so regen the rays
-            if clear_once {
-                clear(black, g);
-                clear_once = false;
+        // re-resolve the scene for the new window size and restart the trace
+        if let Some(args) = e.resize_args() {
+            match Scene::from_json(&scene_file, args.window_size) {
+                Ok(new_scene) => scene = new_scene,
+                Err(err) => show_scene_error(&mut window, &scene_path, &err),
             }
+            let [width, height] = args.window_size;
+            accumulator = raster::Accumulator::new(width as usize, height as usize);
+            texture = None;
+            reset = true;
+        }
 
-            let mut line_coords: Vec<[f64; 4]> = Vec::new();
-            if !reset && reflection_counter <= MAX_REFLECTIONS {
-                let result: physics::ReflectionHandlerResult =
-                    physics::find_closest_mirror_reflections(&rays, &mirrors);
-                // extract new rays
-                rays = result.reflected_rays;
+        if reset {
+            let rays = scene.emit(ray_count as usize, source_pos);
+            println!("resetting...\n\n");
+            worker.restart(scene.clone(), rays, tracer);
+            accumulator.clear();
+            detector_hits.clear();
+            retone = true;
+            reset = false;
+        }
 
-                line_coords = result.draw_line;
+        // add the light of every generation the worker finished to the accumulator
+        if e.render_args().is_some() {
+            for batch in worker.try_batches() {
+                accumulator.splat_segments(&batch.segments, ray_color);
+                detector_hits.extend(batch.detector_hits);
+                retone |= !batch.segments.is_empty();
                 println!(
-                    "rendering set {}, {} rays & {} mirrors for {} intersection checks",
-                    reflection_counter,
-                    rays.len(),
-                    mirrors.len(),
-                    rays.len() * mirrors.len()
+                    "rendering set {}, {} rays & {} mirrors{}{}",
+                    batch.generation,
+                    batch.live_rays,
+                    scene.mirrors().len(),
+                    if batch.pruned_rays > 0 {
+                        format!(", {} weak rays dropped", batch.pruned_rays)
+                    } else {
+                        String::new()
+                    },
+                    if batch.finished { ", done" } else { "" }
                 );
-            } else if reset {
-                rays = physics::generate_rays(RAY_COUNT, mouse_pos);
-                println!("resetting...\n\n");
-                //clear screen
-                clear(black, g);
-                reflection_counter = 0;
-                reset = false;
             }
 
-            reflection_counter += 1;
+            if retone {
+                let canvas = accumulator.tone_map(tone_map, exposure, [0.0, 0.0, 0.0, 1.0]);
+                let size = [canvas.width as u32, canvas.height as u32];
+                let bytes = canvas.to_rgba8();
+                let updated = match &mut texture {
+                    Some(texture) => UpdateTexture::update(
+                        texture,
+                        &mut texture_context,
+                        Format::Rgba8,
+                        &bytes,
+                        [0, 0],
+                        size,
+                    ),
+                    None => CreateTexture::create(
+                        &mut texture_context,
+                        Format::Rgba8,
+                        &bytes,
+                        size,
+                        &TextureSettings::new(),
+                    )
+                    .map(|created| texture = Some(created)),
+                };
+                if let Err(err) = updated {
+                    eprintln!("error updating the picture: {}", err);
+                }
+                retone = false;
+            }
+        }
+
+        // render
+        window.draw_2d(&e, |c: Context, g: &mut G2d, device| {
+            // the texture changes have to reach the gpu before it gets drawn
+            texture_context.encoder.flush(device);
+            // let white = [1.0; 4];
+            let black = [0.0, 0.0, 0.0, 1.0];
+            clear(black, g);
+            if let Some(texture) = &texture {
+                image(texture, c.transform, g);
+            }
 
             // line(black,2f64 ,[100f64, 100f64, 200f64, 200f64], c.transform, g);
 
@@ -120,26 +435,448 @@ fn main() {
             //     rng.random_range(0f32..=1f32),
             //     1.0,
             // ];
-            let dim_yellow:[ColorComponent;4] = [1.0, 1.0, 0.2, 0.02];
-
-            for coords in line_coords {
-                let line_info = [coords[0], coords[1], coords[2], coords[3]];
-                line(dim_yellow, 1.0, line_info, c.transform, g);
-            }
-
             // iterate over mirror vec
-            for mirror in mirrors.clone() {
-                let draw_line = [
-                    mirror.start_pos[0],
-                    mirror.start_pos[1],
-                    mirror.end_pos[0],
-                    mirror.end_pos[1],
-                ];
+            for mirror in scene.mirrors() {
                 let color = mirror.color;
-                line(color, 3.0, draw_line, c.transform, g);
+                // curved mirrors come back as several short lines
+                for draw_line in mirror.outline() {
+                    line(color, 3.0, draw_line, c.transform, g);
+                }
+                // one sided mirrors get ticks on the side that does not reflect
+                for tick in mirror.back_ticks() {
+                    line(color, 1.0, tick, c.transform, g);
+                }
+            }
+
+            if editing {
+                draw_editor(&editor, &scene_file, &scene, c, g);
             }
         });
     }
+    0
+}
+
+// read the scene file and resolve it against the window size
+fn load_scene(
+    path: &str,
+    window_dimensions: [f64; 2],
+) -> Result<(io::JSON, Scene), io::SceneError> {
+    let scene_file = io::read_json(path)?;
+    let scene = Scene::from_json(&scene_file, window_dimensions)?;
+    Ok((scene_file, scene))
+}
+
+// there is no text rendering in the window, so scene errors go to the title bar
+fn show_scene_error(window: &mut PistonWindow, path: &str, err: &io::SceneError) {
+    eprintln!("{}: {}", path, err);
+    window.set_title(format!("{} - {}: {}", WINDOW_TITLE, path, err));
+}
+
+fn window_title(editing: bool) -> String {
+    if editing {
+        format!("{} - edit mode", WINDOW_TITLE)
+    } else {
+        WINDOW_TITLE.to_string()
+    }
+}
+
+// act on what an edit did. returns whether the picture has to start over
+fn apply_edit(
+    window: &mut PistonWindow,
+    path: &str,
+    scene_file: &io::JSON,
+    scene: &mut Scene,
+    change: Result<Change, io::SceneError>,
+) -> bool {
+    match change {
+        Ok(Change::Nothing) => false,
+        Ok(Change::View) => true,
+        Ok(Change::Scene) => {
+            // an edit that breaks the scene (e.g. a mirror dragged onto itself) keeps
+            // showing the last good one until it gets fixed
+            match Scene::from_json(scene_file, [window.size().width, window.size().height]) {
+                Ok(new_scene) => *scene = new_scene,
+                Err(err) => show_scene_error(window, path, &err),
+            }
+            true
+        }
+        Err(err) => {
+            show_scene_error(window, path, &err);
+            false
+        }
+    }
+}
+
+// write the edited scene back over the file it came from, if it is valid
+fn save_scene(window: &mut PistonWindow, path: &str, scene_file: &io::JSON) {
+    if let Some(problem) = io::validate(scene_file).into_iter().next() {
+        show_scene_error(window, path, &problem);
+        return;
+    }
+    match io::write_json(path, scene_file) {
+        Ok(()) => println!("saved {} mirrors to {}", scene_file.mirrors.len(), path),
+        Err(err) => show_scene_error(window, path, &err.into()),
+    }
+}
+
+// highlight the selected mirror with its points, and the mirror being placed
+fn draw_editor(editor: &Editor, scene_file: &io::JSON, scene: &Scene, c: Context, g: &mut G2d) {
+    let highlight = [1.0, 0.4, 0.2, 1.0];
+    let window_dimensions = c.get_view_size();
+    if let Some(selected) = editor.selected {
+        if let Some(mirror) = scene.mirrors().get(selected) {
+            for draw_line in mirror.outline() {
+                line(highlight, raster::MIRROR_WIDTH, draw_line, c.transform, g);
+            }
+        }
+        let handles = editor::handles(scene_file, selected, window_dimensions).unwrap_or_default();
+        for (_, [x, y]) in handles {
+            rectangle(highlight, [x - 4.0, y - 4.0, 8.0, 8.0], c.transform, g);
+        }
+    }
+    if let Some(preview) = editor.preview() {
+        line(highlight, 1.0, preview, c.transform, g);
+    }
+    // lights are only visible through their rays, mark where they can be grabbed
+    let light_color = [1.0, 1.0, 0.0, 1.0];
+    for light in &scene.lights {
+        let [x, y] = light.position();
+        ellipse(light_color, [x - 5.0, y - 5.0, 10.0, 10.0], c.transform, g);
+        if let LightKind::Directional { start, end, .. } | LightKind::Line { start, end } =
+            light.kind
+        {
+            line(
+                light_color,
+                1.0,
+                [start[0], start[1], end[0], end[1]],
+                c.transform,
+                g,
+            );
+            ellipse(
+                light_color,
+                [end[0] - 5.0, end[1] - 5.0, 10.0, 10.0],
+                c.transform,
+                g,
+            );
+        }
+    }
+}
+
+// the light source from the command line, or the middle of the picture
+fn source_position(source: &Option<Vec<f64>>, width: f64, height: f64) -> [f64; 2] {
+    match source {
+        Some(position) => [position[0], position[1]],
+        None => [width / 2f64, height / 2f64],
+    }
+}
+
+// load the scene and run a full trace without a window. record_paths keeps every hit in
+// the trace's path_events
+fn headless_trace(
+    args: &cli::TraceArgs,
+    source: &Option<Vec<f64>>,
+    width: f64,
+    height: f64,
+    record_paths: bool,
+) -> Result<(Scene, piston2d_testing::Trace), io::SceneError> {
+    let scene = Scene::load(&args.scene, [width, height])?;
+    let rays = scene.emit(args.rays, source_position(source, width, height));
+    let tracer = Tracer {
+        record_paths,
+        ..tracer_from_args(args)
+    };
+    let trace = tracer.trace(&scene, rays);
+    Ok((scene, trace))
+}
+
+// trace a scene without a window and write the picture to a png
+fn render(args: cli::RenderArgs) -> i32 {
+    let (width, height) = (args.width, args.height);
+    let (scene, trace) = match headless_trace(
+        &args.trace,
+        &args.source,
+        width as f64,
+        height as f64,
+        false,
+    ) {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("{}: {}", args.trace.scene, err);
+            return 1;
+        }
+    };
+
+    let mut accumulator = raster::Accumulator::new(width, height);
+    accumulator.splat_segments(&trace.segments, raster::RAY_COLOR);
+    let mut canvas =
+        accumulator.tone_map(args.tone.tone_map, args.tone.exposure, [0.0, 0.0, 0.0, 1.0]);
+    raster::draw_mirrors(&mut canvas, &scene);
+    if let Err(err) = canvas.save_png(&args.output) {
+        eprintln!("error writing {}: {}", args.output, err);
+        return 1;
+    }
+    println!(
+        "rendered {} segments over {} bounces to {}",
+        trace.segments.len(),
+        trace.bounces,
+        args.output
+    );
+    0
+}
+
+// trace a scene without a window and write the segments to a file
+fn trace_to_file(args: cli::TraceFileArgs) -> i32 {
+    let (_, trace) = match headless_trace(
+        &args.trace,
+        &args.source,
+        args.width as f64,
+        args.height as f64,
+        false,
+    ) {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("{}: {}", args.trace.scene, err);
+            return 1;
+        }
+    };
+
+    let data = match args.format {
+        cli::TraceFormat::Json => match serde_json::to_string(&trace.segments) {
+            Ok(data) => data,
+            Err(err) => {
+                eprintln!("error encoding segments: {}", err);
+                return 1;
+            }
+        },
+        cli::TraceFormat::Csv => {
+            let mut data = String::from("x1,y1,x2,y2,energy,wavelength\n");
+            for segment in &trace.segments {
+                let [x1, y1, x2, y2] = segment.coords;
+                // rays without a wavelength leave the last column empty
+                let wavelength = segment.wavelength.map_or(String::new(), |w| w.to_string());
+                data.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    x1, y1, x2, y2, segment.energy, wavelength
+                ));
+            }
+            data
+        }
+    };
+    if let Err(err) = std::fs::write(&args.output, data) {
+        eprintln!("error writing {}: {}", args.output, err);
+        return 1;
+    }
+    println!(
+        "wrote {} segments over {} bounces to {}",
+        trace.segments.len(),
+        trace.bounces,
+        args.output
+    );
+    0
+}
+
+// trace a scene without a window and write the path of every ray to a file
+fn paths_to_file(args: cli::PathsArgs) -> i32 {
+    let (_, trace) = match headless_trace(
+        &args.trace,
+        &args.source,
+        args.width as f64,
+        args.height as f64,
+        true,
+    ) {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("{}: {}", args.trace.scene, err);
+            return 1;
+        }
+    };
+
+    let written = std::fs::File::create(&args.output).and_then(|file| {
+        let mut out = std::io::BufWriter::new(file);
+        match args.format {
+            cli::PathFormat::Csv => paths::write_csv(&mut out, &trace.path_events)?,
+            cli::PathFormat::Jsonl => paths::write_json_lines(&mut out, &trace.path_events)?,
+            cli::PathFormat::Binary => paths::write_binary(&mut out, &trace.path_events)?,
+        }
+        std::io::Write::flush(&mut out)
+    });
+    if let Err(err) = written {
+        eprintln!("error writing {}: {}", args.output, err);
+        return 1;
+    }
+    println!(
+        "wrote {} path events over {} bounces to {}",
+        trace.path_events.len(),
+        trace.bounces,
+        args.output
+    );
+    0
+}
+
+// what the detectors got, binned into profiles or hit by hit
+fn measurements(
+    scene: &Scene,
+    hits: &[DetectorHit],
+    format: cli::TraceFormat,
+    every_hit: bool,
+) -> serde_json::Result<String> {
+    let profiles = || detector::profiles(scene.mirrors(), hits);
+    match (format, every_hit) {
+        (cli::TraceFormat::Json, true) => serde_json::to_string(hits),
+        (cli::TraceFormat::Json, false) => serde_json::to_string(&profiles()),
+        (cli::TraceFormat::Csv, true) => Ok(detector::hits_csv(hits)),
+        (cli::TraceFormat::Csv, false) => Ok(detector::profiles_csv(&profiles())),
+    }
+}
+
+// trace a scene without a window and write what its detectors measured to a file
+fn measure(args: cli::MeasureArgs) -> i32 {
+    let (scene, trace) = match headless_trace(
+        &args.trace,
+        &args.source,
+        args.width as f64,
+        args.height as f64,
+        false,
+    ) {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("{}: {}", args.trace.scene, err);
+            return 1;
+        }
+    };
+    let data = match measurements(&scene, &trace.detector_hits, args.format, args.hits) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("error encoding measurements: {}", err);
+            return 1;
+        }
+    };
+    if let Err(err) = std::fs::write(&args.output, data) {
+        eprintln!("error writing {}: {}", args.output, err);
+        return 1;
+    }
+    println!(
+        "wrote {} detector hits over {} bounces to {}",
+        trace.detector_hits.len(),
+        trace.bounces,
+        args.output
+    );
+    0
+}
+
+fn scene_command(command: SceneCommand) -> i32 {
+    match command {
+        SceneCommand::Validate {
+            path,
+            width,
+            height,
+        } => {
+            let scene_file = match io::read_json(&path) {
+                Ok(scene_file) => scene_file,
+                Err(err) => {
+                    eprintln!("{}: {}", path, err);
+                    return 1;
+                }
+            };
+            // list every problem at once instead of making people fix them one by one
+            let problems = io::validate(&scene_file);
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("{}: {}", path, problem);
+                }
+                eprintln!("{}: {} problems", path, problems.len());
+                return 1;
+            }
+            match Scene::from_json(&scene_file, [width, height]) {
+                Ok(scene) => {
+                    println!(
+                        "{}: ok, version {}, {} mirrors",
+                        path,
+                        scene_file.version,
+                        scene.mirrors().len()
+                    );
+                    0
+                }
+                Err(err) => {
+                    eprintln!("{}: {}", path, err);
+                    1
+                }
+            }
+        }
+        SceneCommand::Convert {
+            input,
+            output,
+            to,
+            width,
+            height,
+            scale,
+            rotate,
+            translate,
+            origin,
+        } => {
+            // converting in place loses the original if anything about the conversion is off
+            if same_file(&input, &output) {
+                eprintln!(
+                    "refusing to overwrite the input {}, pick another output",
+                    input
+                );
+                return 2;
+            }
+            let scene_file = match io::read_json(&input) {
+                Ok(scene_file) => scene_file,
+                Err(err) => {
+                    eprintln!("{}: {}", input, err);
+                    return 1;
+                }
+            };
+            let to: CoordFormat = match to.as_ref().unwrap_or(&scene_file.coord_format).parse() {
+                Ok(to) => to,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return 2;
+                }
+            };
+
+            // scale, then rotate, then translate
+            let origin = origin.map_or([width / 2f64, height / 2f64], |o| [o[0], o[1]]);
+            let mut transforms: Vec<io::Transform> = Vec::new();
+            if let Some(scale) = scale {
+                let factor = [scale[0], *scale.get(1).unwrap_or(&scale[0])];
+                transforms.push(io::Transform::Scale { factor, origin });
+            }
+            if let Some(degrees) = rotate {
+                transforms.push(io::Transform::Rotate { degrees, origin });
+            }
+            if let Some(offset) = translate {
+                transforms.push(io::Transform::Translate {
+                    offset: [offset[0], offset[1]],
+                });
+            }
+
+            let converted = match io::transform_scene(&scene_file, to, &transforms, [width, height])
+            {
+                Ok(converted) => converted,
+                Err(err) => {
+                    eprintln!("{}: {}", input, err);
+                    return 1;
+                }
+            };
+            if let Err(err) = io::write_json(&output, &converted) {
+                eprintln!("error writing {}: {}", output, err);
+                return 1;
+            }
+            println!("converted {} to {} as {}", input, output, to.as_str());
+            0
+        }
+    }
+}
+
+// whether two paths point at the same file, also when they are spelled differently
+fn same_file(a: &str, b: &str) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
 }
 
 // use this function to simplify draw calls. maybe pass the draw args in and get em out?
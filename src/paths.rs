@@ -0,0 +1,218 @@
+use serde::Serialize;
+use std::io::{self, Read, Write};
+
+// one ray arriving at a surface, and one of the rays leaving it. a hit that splits the
+// ray (glass) gives one event per outgoing ray, a hit nothing leaves (a detector) one
+// event without an outgoing direction. rays that leave the scene without hitting
+// anything have no event for that last stretch
+#[derive(Serialize, Debug, Copy, Clone, PartialEq)]
+pub struct PathEvent {
+    // id of the emitted ray this path started as, shared by everything split off it
+    pub ray: usize,
+    // surfaces the ray went past before this one, 0 on the first hit
+    pub bounce: usize,
+    // index of the mirror that was hit
+    pub mirror: usize,
+    pub position: [f64; 2],
+    // unit direction the ray arrived in, and the energy it arrived with
+    pub incident: [f64; 2],
+    pub energy: f64,
+    // unit direction of the ray leaving, and the energy it took along. rays leaving with
+    // less than the energy threshold are listed too, they are just not traced further
+    pub outgoing: Option<[f64; 2]>,
+    pub outgoing_energy: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wavelength: Option<f64>,
+}
+
+pub(crate) fn unit(vector: [f64; 2]) -> [f64; 2] {
+    let length = (vector[0] * vector[0] + vector[1] * vector[1]).sqrt();
+    [vector[0] / length, vector[1] / length]
+}
+
+// one line per event. a missing outgoing ray or wavelength leaves its columns empty
+pub fn write_csv(out: &mut impl Write, events: &[PathEvent]) -> io::Result<()> {
+    writeln!(
+        out,
+        "ray,bounce,mirror,x,y,incident_x,incident_y,energy,\
+         outgoing_x,outgoing_y,outgoing_energy,wavelength"
+    )?;
+    for event in events {
+        let (outgoing_x, outgoing_y) = match event.outgoing {
+            Some([x, y]) => (x.to_string(), y.to_string()),
+            None => (String::new(), String::new()),
+        };
+        let wavelength = event.wavelength.map_or(String::new(), |w| w.to_string());
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            event.ray,
+            event.bounce,
+            event.mirror,
+            event.position[0],
+            event.position[1],
+            event.incident[0],
+            event.incident[1],
+            event.energy,
+            outgoing_x,
+            outgoing_y,
+            event.outgoing_energy,
+            wavelength
+        )?;
+    }
+    Ok(())
+}
+
+// one json object per line, so big traces can be streamed instead of parsed in one go
+pub fn write_json_lines(out: &mut impl Write, events: &[PathEvent]) -> io::Result<()> {
+    for event in events {
+        serde_json::to_writer(&mut *out, event)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// the binary format starts with this, then the format version and the record size as
+// little endian u32s
+pub const BINARY_MAGIC: &[u8; 8] = b"RAYPATH\0";
+pub const BINARY_VERSION: u32 = 1;
+
+// every event is one fixed size little endian record:
+// ray u64, bounce u32, mirror u32, then f64s for x, y, incident x and y, energy,
+// outgoing x and y, outgoing energy and wavelength. a missing outgoing ray or
+// wavelength is nan. with numpy that is
+// dtype([("ray", "<u8"), ("bounce", "<u4"), ("mirror", "<u4"), ("x", "<f8"), ("y", "<f8"),
+//        ("incident_x", "<f8"), ("incident_y", "<f8"), ("energy", "<f8"),
+//        ("outgoing_x", "<f8"), ("outgoing_y", "<f8"), ("outgoing_energy", "<f8"),
+//        ("wavelength", "<f8")])
+// read with offset = 16
+pub const BINARY_RECORD_SIZE: usize = 8 + 4 + 4 + 9 * 8;
+
+pub fn write_binary(out: &mut impl Write, events: &[PathEvent]) -> io::Result<()> {
+    out.write_all(BINARY_MAGIC)?;
+    out.write_all(&BINARY_VERSION.to_le_bytes())?;
+    out.write_all(&(BINARY_RECORD_SIZE as u32).to_le_bytes())?;
+    for event in events {
+        let [outgoing_x, outgoing_y] = event.outgoing.unwrap_or([f64::NAN; 2]);
+        out.write_all(&(event.ray as u64).to_le_bytes())?;
+        out.write_all(&(event.bounce as u32).to_le_bytes())?;
+        out.write_all(&(event.mirror as u32).to_le_bytes())?;
+        for value in [
+            event.position[0],
+            event.position[1],
+            event.incident[0],
+            event.incident[1],
+            event.energy,
+            outgoing_x,
+            outgoing_y,
+            event.outgoing_energy,
+            event.wavelength.unwrap_or(f64::NAN),
+        ] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+// read back what write_binary wrote
+pub fn read_binary(input: &mut impl Read) -> io::Result<Vec<PathEvent>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut header = [0u8; 16];
+    input.read_exact(&mut header)?;
+    if &header[0..8] != BINARY_MAGIC {
+        return Err(invalid("not a ray path file"));
+    }
+    let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let record_size = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+    if version != BINARY_VERSION || record_size != BINARY_RECORD_SIZE {
+        return Err(invalid("unsupported ray path file version"));
+    }
+
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    if data.len() % record_size != 0 {
+        return Err(invalid("ray path file ends in the middle of a record"));
+    }
+    let events = data
+        .chunks_exact(record_size)
+        .map(|record| {
+            let float = |n: usize| {
+                let start = 16 + n * 8;
+                f64::from_le_bytes(record[start..start + 8].try_into().unwrap())
+            };
+            let present = |value: f64| (!value.is_nan()).then_some(value);
+            PathEvent {
+                ray: u64::from_le_bytes(record[0..8].try_into().unwrap()) as usize,
+                bounce: u32::from_le_bytes(record[8..12].try_into().unwrap()) as usize,
+                mirror: u32::from_le_bytes(record[12..16].try_into().unwrap()) as usize,
+                position: [float(0), float(1)],
+                incident: [float(2), float(3)],
+                energy: float(4),
+                outgoing: present(float(5)).map(|x| [x, float(6)]),
+                outgoing_energy: float(7),
+                wavelength: present(float(8)),
+            }
+        })
+        .collect();
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<PathEvent> {
+        let hit = PathEvent {
+            ray: 12,
+            bounce: 3,
+            mirror: 4,
+            position: [10.5, -2.0],
+            incident: [0.6, 0.8],
+            energy: 0.75,
+            outgoing: Some([0.6, -0.8]),
+            outgoing_energy: 0.5,
+            wavelength: Some(532.0),
+        };
+        let swallowed = PathEvent {
+            outgoing: None,
+            outgoing_energy: 0.0,
+            wavelength: None,
+            ..hit
+        };
+        vec![hit, swallowed]
+    }
+
+    #[test]
+    fn binary_paths_read_back_the_same() {
+        let mut data = Vec::new();
+        write_binary(&mut data, &events()).unwrap();
+        assert_eq!(data.len(), 16 + 2 * BINARY_RECORD_SIZE);
+        assert_eq!(read_binary(&mut data.as_slice()).unwrap(), events());
+
+        // a cut off file is an error, not a shorter list
+        data.pop();
+        assert!(read_binary(&mut data.as_slice()).is_err());
+    }
+
+    #[test]
+    fn text_formats_have_a_line_per_event() {
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &events()).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "12,3,4,10.5,-2,0.6,0.8,0.75,0.6,-0.8,0.5,532");
+        assert_eq!(lines[2], "12,3,4,10.5,-2,0.6,0.8,0.75,,,0,");
+
+        let mut json = Vec::new();
+        write_json_lines(&mut json, &events()).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        let parsed: Vec<serde_json::Value> = json
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0]["outgoing"][1], -0.8);
+        assert!(parsed[1]["outgoing"].is_null());
+    }
+}
@@ -1,8 +1,16 @@
-use piston_window::types::{Color, ColorComponent};
-use rand::prelude::*;
-use std::cell::Ref;
+use crate::bvh::MirrorBvh;
+use crate::coords::CoordFormat;
+use crate::detector::{self, DetectorHit};
 use crate::io;
-use crate::io::JSON;
+use crate::paths::{self, PathEvent};
+use crate::spectrum::{RefractiveIndex, REFERENCE_WAVELENGTH};
+use rand::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+
+// one channel of an rgba color. same as piston's ColorComponent, so colors can go straight
+// into the draw calls without the physics depending on a window
+pub type ColorComponent = f32;
 
 // structures to wrap return statements that need a success and data iff success
 #[derive(Debug, Copy, Clone)]
@@ -20,53 +28,88 @@ impl IntersectResult {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-struct ReflectResult {
-    success: bool,
-    start_pos: [f64; 2],
-    end_pos: [f64; 2],
+// a traced piece of a ray path, with the energy the ray carried along it
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct Segment {
+    pub coords: [f64; 4],
+    pub energy: f64,
+    // wavelength of the ray in nanometers, for rays from a light with a spectrum
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wavelength: Option<f64>,
 }
 
-impl ReflectResult {
-    fn empty() -> ReflectResult {
-        ReflectResult {
-            success: false,
-            start_pos: [f64::INFINITY; 2],
-            end_pos: [f64::INFINITY; 2],
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ReflectionHandlerResult {
-    pub draw_line: Vec<[f64; 4]>,
+    pub draw_line: Vec<Segment>,
     pub reflected_rays: Vec<Ray>,
+    // rays that ended on a detector this generation
+    pub detector_hits: Vec<DetectorHit>,
+    // every hit of this generation, only filled in when asked for
+    pub path_events: Vec<PathEvent>,
+    // rays dropped to stay under the tracer's max_rays, and the energy they carried
+    pub pruned_rays: usize,
+    pub pruned_energy: f64,
+    // energy of every pruned ray, summed into pruned_energy by finish
+    pruned_energies: Vec<f64>,
 }
 
 impl ReflectionHandlerResult {
+    fn prune(&mut self, max_rays: usize) {
+        let dropped = prune_weakest(&mut self.reflected_rays, max_rays);
+        self.pruned_rays += dropped.len();
+        self.pruned_energies.extend(dropped);
+    }
+
+    // add up the pruned energy. serial and parallel traces drop the same rays, but at
+    // different times, so the energies get sorted first to add them up in the same order
+    fn finish(mut self) -> ReflectionHandlerResult {
+        let mut energies = std::mem::take(&mut self.pruned_energies);
+        energies.sort_by(f64::total_cmp);
+        self.pruned_energy = energies.into_iter().sum();
+        self
+    }
+
     fn empty() -> ReflectionHandlerResult {
         ReflectionHandlerResult {
             draw_line: vec![],
             reflected_rays: vec![],
+            detector_hits: vec![],
+            path_events: vec![],
+            pruned_rays: 0,
+            pruned_energy: 0.0,
+            pruned_energies: vec![],
         }
     }
 }
 
 // structures for Ray and Mirror
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Ray {
-    pub(crate) start_pos: [f64; 2],
-    pub(crate) vector: [f64; 2],
-    pub(crate) color: [ColorComponent; 4],
+    pub start_pos: [f64; 2],
+    pub vector: [f64; 2],
+    pub color: [ColorComponent; 4],
+    // fraction of the emitted energy the ray still carries. 1 at the source
+    pub energy: f64,
+    // wavelength in nanometers. rays without one are drawn in the plain ray color and see
+    // the refractive index at spectrum::REFERENCE_WAVELENGTH
+    pub wavelength: Option<f64>,
+    // surfaces the ray or its parents bounced off or went through since leaving the light
+    pub bounces: usize,
+    // number of the emitted ray this one descends from, see paths::PathEvent
+    pub id: usize,
 }
 
 impl Ray {
     // generate new ray object from input variables
-    fn new(start_pos: [f64; 2], vector: [f64; 2], color: [ColorComponent; 4]) -> Ray {
+    pub fn new(start_pos: [f64; 2], vector: [f64; 2], color: [ColorComponent; 4]) -> Ray {
         Ray {
             start_pos,
             vector,
             color,
+            energy: 1.0,
+            wavelength: None,
+            bounces: 0,
+            id: 0,
         }
     }
 
@@ -77,7 +120,7 @@ impl Ray {
         ((self.start_pos[0] - point[0]).powi(2) + (self.start_pos[1] - point[1]).powi(2)).sqrt()
     }
 
-    fn reflect(&self, position: [f64; 2], normal: [f64; 2]) -> Ray {
+    fn reflect(&self, position: [f64; 2], normal: [f64; 2], absorption_factor: f64) -> Ray {
         // split the vector reflection equation into multiple parts to make things readable
         let norm_squared: f64 = normal[0] * normal[0] + normal[1] * normal[1];
         let dot_product: f64 = self.vector[0] * normal[0] + self.vector[1] * normal[1];
@@ -87,33 +130,573 @@ impl Ray {
             self.vector[1] - 2f64 * dot_product / norm_squared * normal[1],
         ];
 
-        // create new object ray and return it. for now colors are preserved.
+        // create new object ray and return it. colors and wavelengths are preserved,
+        // the mirror only takes away its share of the energy
         Ray {
             start_pos: position,
             vector: result,
-            color: self.color,
+            energy: self.energy * (1f64 - absorption_factor),
+            bounces: self.bounces + 1,
+            ..*self
+        }
+    }
+
+    // one of the rays a rough surface sends off from `position`, carrying `energy`
+    fn scatter(&self, position: [f64; 2], direction: [f64; 2], energy: f64) -> Ray {
+        Ray {
+            start_pos: position,
+            vector: direction,
+            energy,
+            bounces: self.bounces + 1,
+            ..*self
+        }
+    }
+
+    // split the ray at a boundary between two media. n1 is the refractive index on the side
+    // the ray comes from, n2 the one it enters. returns the reflected ray and, unless there
+    // is total internal reflection, the refracted ray, each carrying its fresnel share
+    fn refract(
+        &self,
+        position: [f64; 2],
+        normal: [f64; 2],
+        n1: f64,
+        n2: f64,
+        absorption_factor: f64,
+    ) -> (Ray, Option<Ray>) {
+        // unit direction and a unit normal that faces against the incoming ray
+        let length = (self.vector[0].powi(2) + self.vector[1].powi(2)).sqrt();
+        let direction = [self.vector[0] / length, self.vector[1] / length];
+        let normal_length = (normal[0].powi(2) + normal[1].powi(2)).sqrt();
+        let mut unit_normal = [normal[0] / normal_length, normal[1] / normal_length];
+        let mut cos_i = -(direction[0] * unit_normal[0] + direction[1] * unit_normal[1]);
+        if cos_i < 0.0 {
+            unit_normal = [-unit_normal[0], -unit_normal[1]];
+            cos_i = -cos_i;
+        }
+
+        let mut reflected = self.reflect(position, unit_normal, absorption_factor);
+
+        // snell's law: n1 sin(i) = n2 sin(t)
+        let eta = n1 / n2;
+        let sin_t_squared = eta * eta * (1f64 - cos_i * cos_i);
+        if sin_t_squared > 1.0 {
+            // total internal reflection, everything bounces back
+            return (reflected, None);
+        }
+        let cos_t = (1f64 - sin_t_squared).sqrt();
+
+        // fresnel equations for unpolarized light: average the s and p reflectances
+        let r_s = ((n1 * cos_i - n2 * cos_t) / (n1 * cos_i + n2 * cos_t)).powi(2);
+        let r_p = ((n1 * cos_t - n2 * cos_i) / (n1 * cos_t + n2 * cos_i)).powi(2);
+        let reflectance = (r_s + r_p) / 2f64;
+
+        let refracted = Ray {
+            start_pos: position,
+            vector: [
+                eta * direction[0] + (eta * cos_i - cos_t) * unit_normal[0],
+                eta * direction[1] + (eta * cos_i - cos_t) * unit_normal[1],
+            ],
+            energy: reflected.energy * (1f64 - reflectance),
+            bounces: self.bounces + 1,
+            ..*self
+        };
+        reflected.energy *= reflectance;
+
+        (reflected, Some(refracted))
+    }
+}
+
+// the geometry of a mirror. start_pos and end_pos are always the two ends of the shape
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Shape {
+    // straight line between start_pos and end_pos
+    Segment,
+    // piece of a circle, running from start_angle over sweep radians (negative = clockwise)
+    Arc {
+        center: [f64; 2],
+        radius: f64,
+        start_angle: f64,
+        sweep: f64,
+    },
+    // piece of the parabola v = u^2 / (4 * focal_length) in the frame of the vertex,
+    // where v runs along `axis` (unit vector towards the focus) and u along `[axis[1], -axis[0]]`.
+    // the segment covers u in [u_min, u_max]
+    Parabola {
+        vertex: [f64; 2],
+        axis: [f64; 2],
+        focal_length: f64,
+        u_min: f64,
+        u_max: f64,
+    },
+}
+
+// the one tolerance every geometric test uses, in pixels. two points closer than this are
+// the same point:
+// - hits closer to the ray origin are the surface the ray just left
+// - hits closer to a mirror end are on that end, and snap to it exactly
+// - a ray drifting less than this off a mirror's line over the mirror's length runs
+//   parallel to it, and along it if it starts closer than this to that line
+pub const EPSILON: f64 = 1e-7;
+
+// number of straight pieces used to draw a curved mirror
+const CURVE_DRAW_STEPS: usize = 48;
+
+// real roots of a * x^2 + b * x + c = 0, smallest first. falls back to the linear
+// equation when a vanishes, which happens for rays parallel to a parabola's axis
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return vec![];
         }
+        return vec![-c / b];
     }
+    let discriminant = b * b - 4f64 * a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+    let root = discriminant.sqrt();
+    // numerically stable form, avoids cancellation between -b and root
+    let q = -0.5 * (b + b.signum() * root);
+    let mut roots = if q == 0.0 {
+        vec![0.0]
+    } else {
+        vec![q / a, c / q]
+    };
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots
 }
 
+// what a surface does with the light that hits it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Surface {
+    // perfect specular reflector
+    Mirror,
+    // boundary between two transparent media. the front side is the one the normal points to
+    Dielectric {
+        refractive_index_front: RefractiveIndex,
+        refractive_index_back: RefractiveIndex,
+    },
+    // swallows every ray and records where and how it arrived, see detector::DetectorHit.
+    // the recorded hits get split into `bins` equal pieces along the detector
+    Detector {
+        bins: usize,
+    },
+    // lambertian: sends `samples` rays off in random directions, more of them along the
+    // normal than sideways, like a matte wall
+    Diffuse {
+        samples: usize,
+    },
+    // phong lobe: sends `samples` rays off around the mirror direction. the higher the
+    // shininess, the tighter the lobe
+    Glossy {
+        shininess: f64,
+        samples: usize,
+    },
+    // beam splitter: every hit sends off two rays, `reflectance` of the energy left after
+    // absorption along the mirror direction and the rest straight on through
+    Splitter {
+        reflectance: f64,
+    },
+}
+
+// rays a diffuse or glossy hit sends off unless the scene says otherwise
+pub const DEFAULT_SAMPLES: usize = 1;
+// phong exponent of glossy surfaces unless the scene says otherwise
+pub const DEFAULT_SHININESS: f64 = 20.0;
+// share of the glossy lobe's peak below which angles are never drawn
+const GLOSSY_CUTOFF: f64 = 1e-9;
+// share of the light a splitter reflects unless the scene says otherwise
+pub const DEFAULT_REFLECTANCE: f64 = 0.5;
+
+// seed for the scattering at one hit, from the trace seed, the ray and where it hit. a hit
+// scatters the same way whichever thread traces it and in whatever order
+fn hit_seed(seed: u64, ray: &Ray, position: [f64; 2]) -> u64 {
+    // splitmix64 over every part
+    [
+        ray.id as u64,
+        ray.bounces as u64,
+        position[0].to_bits(),
+        position[1].to_bits(),
+    ]
+    .iter()
+    .fold(seed, |hash, value| {
+        let mut z = (hash ^ value).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    })
+}
+
+// `vector` turned by `angle` radians
+fn rotate(vector: [f64; 2], angle: f64) -> [f64; 2] {
+    let (sin, cos) = angle.sin_cos();
+    [
+        vector[0] * cos - vector[1] * sin,
+        vector[0] * sin + vector[1] * cos,
+    ]
+}
+
+// which sides of a surface take part
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sides {
+    // both sides reflect, glass has its front where normal() points
+    Both,
+    // only the front, on the given side of the way from start_pos to end_pos on screen.
+    // mirrors and detectors let light reaching the back do what `back` says
+    One { front: io::Side, back: io::BackFace },
+}
+
+// length of the ticks drawn on the back of one sided mirrors, and the room between them
+const TICK_LENGTH: f64 = 6.0;
+const TICK_SPACING: f64 = 10.0;
+
 #[derive(Debug, Copy, Clone)]
 pub struct Mirror {
-    pub(crate) start_pos: [f64; 2],
-    pub(crate) end_pos: [f64; 2],
-    pub(crate) color: [ColorComponent; 4],
+    pub start_pos: [f64; 2],
+    pub end_pos: [f64; 2],
+    pub color: [ColorComponent; 4],
+    // 0 = perfect mirror, 1 = nothing gets reflected
+    pub absorption_factor: f64,
+    pub surface: Surface,
+    pub shape: Shape,
+    pub sides: Sides,
 }
 
 impl Mirror {
-    // create an empty mirror object with color black
-    fn empty() -> Mirror {
+    // build a straight mirror between two points
+    pub fn segment(
+        start_pos: [f64; 2],
+        end_pos: [f64; 2],
+        color: [ColorComponent; 4],
+        absorption_factor: f64,
+        surface: Surface,
+    ) -> Mirror {
         Mirror {
-            start_pos: [f64::INFINITY; 2],
-            end_pos: [f64::INFINITY; 2],
-            color: [0.0, 0.0, 0.0, 1.0],
+            start_pos,
+            end_pos,
+            color,
+            absorption_factor,
+            surface,
+            shape: Shape::Segment,
+            sides: Sides::Both,
         }
     }
+
+    // build a circular arc mirror that starts at start_pos, passes through through_pos and
+    // ends at end_pos. three points on a line give a straight segment instead
+    pub fn arc(
+        start_pos: [f64; 2],
+        through_pos: [f64; 2],
+        end_pos: [f64; 2],
+        color: [ColorComponent; 4],
+        absorption_factor: f64,
+        surface: Surface,
+    ) -> Mirror {
+        let mut mirror = Mirror {
+            start_pos,
+            end_pos,
+            color,
+            absorption_factor,
+            surface,
+            shape: Shape::Segment,
+            sides: Sides::Both,
+        };
+
+        // circumcenter of the three points
+        let [ax, ay] = start_pos;
+        let [bx, by] = through_pos;
+        let [cx, cy] = end_pos;
+        let d = 2f64 * (ax * (by - cy) + bx * (cy - ay) + cx * (ay - by));
+        // d is twice the through point's distance from the line between the ends, times
+        // the length of that line. closer than EPSILON to it, the three points are a line
+        if d.abs() <= 2f64 * EPSILON * (cx - ax).hypot(cy - ay) {
+            return mirror;
+        }
+        let a_sq = ax * ax + ay * ay;
+        let b_sq = bx * bx + by * by;
+        let c_sq = cx * cx + cy * cy;
+        let center = [
+            (a_sq * (by - cy) + b_sq * (cy - ay) + c_sq * (ay - by)) / d,
+            (a_sq * (cx - bx) + b_sq * (ax - cx) + c_sq * (bx - ax)) / d,
+        ];
+        let radius = ((ax - center[0]).powi(2) + (ay - center[1]).powi(2)).sqrt();
+
+        // go counter clockwise from start to end if that passes the through point,
+        // otherwise go the other way around
+        let tau = std::f64::consts::TAU;
+        let angle_of = |point: [f64; 2]| (point[1] - center[1]).atan2(point[0] - center[0]);
+        let start_angle = angle_of(start_pos);
+        let to_end = (angle_of(end_pos) - start_angle).rem_euclid(tau);
+        let to_through = (angle_of(through_pos) - start_angle).rem_euclid(tau);
+        let sweep = if to_through < to_end {
+            to_end
+        } else {
+            to_end - tau
+        };
+
+        mirror.shape = Shape::Arc {
+            center,
+            radius,
+            start_angle,
+            sweep,
+        };
+        mirror
+    }
+
+    // build a parabolic mirror from its vertex and focus. the ends of the mirror are where
+    // start_pos and end_pos project onto the parabola across its axis
+    pub fn parabola(
+        vertex: [f64; 2],
+        focus: [f64; 2],
+        start_pos: [f64; 2],
+        end_pos: [f64; 2],
+        color: [ColorComponent; 4],
+        absorption_factor: f64,
+        surface: Surface,
+    ) -> Mirror {
+        let focal_length = ((focus[0] - vertex[0]).powi(2) + (focus[1] - vertex[1]).powi(2)).sqrt();
+        let mut mirror = Mirror {
+            start_pos,
+            end_pos,
+            color,
+            absorption_factor,
+            surface,
+            shape: Shape::Segment,
+            sides: Sides::Both,
+        };
+        if focal_length <= EPSILON {
+            return mirror;
+        }
+
+        let axis = [
+            (focus[0] - vertex[0]) / focal_length,
+            (focus[1] - vertex[1]) / focal_length,
+        ];
+        let across = [axis[1], -axis[0]];
+        let u_of = |point: [f64; 2]| {
+            (point[0] - vertex[0]) * across[0] + (point[1] - vertex[1]) * across[1]
+        };
+        let (u_start, u_end) = (u_of(start_pos), u_of(end_pos));
+
+        mirror.shape = Shape::Parabola {
+            vertex,
+            axis,
+            focal_length,
+            u_min: u_start.min(u_end),
+            u_max: u_start.max(u_end),
+        };
+        // snap the ends onto the curve
+        mirror.start_pos = mirror.point_at(u_start);
+        mirror.end_pos = mirror.point_at(u_end);
+        mirror
+    }
+
+    // point on a curved mirror for the curve parameter t (angle for arcs, u for parabolas)
+    fn point_at(&self, t: f64) -> [f64; 2] {
+        match self.shape {
+            Shape::Segment => [
+                self.start_pos[0] + t * (self.end_pos[0] - self.start_pos[0]),
+                self.start_pos[1] + t * (self.end_pos[1] - self.start_pos[1]),
+            ],
+            Shape::Arc { center, radius, .. } => {
+                [center[0] + radius * t.cos(), center[1] + radius * t.sin()]
+            }
+            Shape::Parabola {
+                vertex,
+                axis,
+                focal_length,
+                ..
+            } => {
+                let v = t * t / (4f64 * focal_length);
+                [
+                    vertex[0] + t * axis[1] + v * axis[0],
+                    vertex[1] - t * axis[0] + v * axis[1],
+                ]
+            }
+        }
+    }
+
+    // straight pieces to draw this mirror with
+    pub fn outline(&self) -> Vec<[f64; 4]> {
+        let (t_start, t_end) = match self.shape {
+            Shape::Segment => {
+                return vec![[
+                    self.start_pos[0],
+                    self.start_pos[1],
+                    self.end_pos[0],
+                    self.end_pos[1],
+                ]]
+            }
+            Shape::Arc {
+                start_angle, sweep, ..
+            } => (start_angle, start_angle + sweep),
+            Shape::Parabola { u_min, u_max, .. } => (u_min, u_max),
+        };
+        let mut pieces = Vec::with_capacity(CURVE_DRAW_STEPS);
+        let mut previous = self.point_at(t_start);
+        for step in 1..=CURVE_DRAW_STEPS {
+            let t = t_start + (t_end - t_start) * step as f64 / CURVE_DRAW_STEPS as f64;
+            let next = self.point_at(t);
+            pieces.push([previous[0], previous[1], next[0], next[1]]);
+            previous = next;
+        }
+        pieces
+    }
+
     // intersection function for a ray as input
     fn intersect(&self, ray: Ray) -> IntersectResult {
+        match self.shape {
+            Shape::Segment => self.intersect_segment(ray),
+            Shape::Arc {
+                center,
+                radius,
+                start_angle,
+                sweep,
+            } => {
+                // solve |p + m * v - center| = radius for the ray factor m
+                let offset = [ray.start_pos[0] - center[0], ray.start_pos[1] - center[1]];
+                let a = ray.vector[0].powi(2) + ray.vector[1].powi(2);
+                let b = 2f64 * (ray.vector[0] * offset[0] + ray.vector[1] * offset[1]);
+                let c = offset[0].powi(2) + offset[1].powi(2) - radius * radius;
+                let on_arc = |position: [f64; 2]| {
+                    let angle = (position[1] - center[1]).atan2(position[0] - center[0]);
+                    if sweep >= 0.0 {
+                        (angle - start_angle).rem_euclid(std::f64::consts::TAU) <= sweep
+                    } else {
+                        (start_angle - angle).rem_euclid(std::f64::consts::TAU) <= -sweep
+                    }
+                };
+                self.first_curve_hit(ray, solve_quadratic(a, b, c), on_arc)
+            }
+            Shape::Parabola {
+                vertex,
+                axis,
+                focal_length,
+                u_min,
+                u_max,
+            } => {
+                // move the ray into the parabola frame and solve v = u^2 / 4f
+                let across = [axis[1], -axis[0]];
+                let offset = [ray.start_pos[0] - vertex[0], ray.start_pos[1] - vertex[1]];
+                let qu = offset[0] * across[0] + offset[1] * across[1];
+                let qv = offset[0] * axis[0] + offset[1] * axis[1];
+                let du = ray.vector[0] * across[0] + ray.vector[1] * across[1];
+                let dv = ray.vector[0] * axis[0] + ray.vector[1] * axis[1];
+                // a ray drifting across the axis less than EPSILON per pixel it travels runs
+                // parallel to it, and only meets the parabola once
+                let speed = du.hypot(dv);
+                let a = if du.abs() <= EPSILON * speed {
+                    0.0
+                } else {
+                    du * du
+                };
+                let b = 2f64 * qu * du - 4f64 * focal_length * dv;
+                let c = qu * qu - 4f64 * focal_length * qv;
+                let on_segment = |position: [f64; 2]| {
+                    let u = (position[0] - vertex[0]) * across[0]
+                        + (position[1] - vertex[1]) * across[1];
+                    u_min <= u && u <= u_max
+                };
+                self.first_curve_hit(ray, solve_quadratic(a, b, c), on_segment)
+            }
+        }
+    }
+
+    // where the ray hits this mirror and how far it travels to get there. hits right at the
+    // ray origin are ignored, that is the mirror the ray is leaving from
+    pub(crate) fn hit(&self, ray: &Ray) -> Option<([f64; 2], f64)> {
+        let intersect = self.intersect(*ray);
+        if !intersect.success {
+            return None;
+        }
+        let distance = ray.distance(intersect.position);
+        if distance > EPSILON {
+            Some((intersect.position, distance))
+        } else {
+            None
+        }
+    }
+
+    // axis aligned box [min_x, min_y, max_x, max_y] that contains the whole mirror
+    pub(crate) fn bounds(&self) -> [f64; 4] {
+        let mut points = vec![self.start_pos, self.end_pos];
+        match self.shape {
+            Shape::Segment => {}
+            Shape::Arc {
+                center,
+                radius,
+                start_angle,
+                sweep,
+            } => {
+                // the arc can bulge past its ends where it crosses the circle's axes
+                for quarter in 0..4 {
+                    let angle = quarter as f64 * std::f64::consts::FRAC_PI_2;
+                    let inside = if sweep >= 0.0 {
+                        (angle - start_angle).rem_euclid(std::f64::consts::TAU) <= sweep
+                    } else {
+                        (start_angle - angle).rem_euclid(std::f64::consts::TAU) <= -sweep
+                    };
+                    if inside {
+                        points.push([
+                            center[0] + radius * angle.cos(),
+                            center[1] + radius * angle.sin(),
+                        ]);
+                    }
+                }
+            }
+            Shape::Parabola { u_min, u_max, .. } => {
+                // a parabola piece is a quadratic bezier curve, so it stays inside the
+                // triangle of its ends and the point where their tangents meet
+                let start = self.point_at(u_min);
+                let middle = self.point_at((u_min + u_max) / 2f64);
+                let end = self.point_at(u_max);
+                points.push([
+                    2f64 * middle[0] - (start[0] + end[0]) / 2f64,
+                    2f64 * middle[1] - (start[1] + end[1]) / 2f64,
+                ]);
+            }
+        }
+        let mut bounds = [f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY];
+        for point in points {
+            bounds[0] = bounds[0].min(point[0]);
+            bounds[1] = bounds[1].min(point[1]);
+            bounds[2] = bounds[2].max(point[0]);
+            bounds[3] = bounds[3].max(point[1]);
+        }
+        bounds
+    }
+
+    // pick the closest of the candidate ray factors that lands on the curve. like on
+    // segments, a hit within EPSILON of an end is on that end, exactly
+    fn first_curve_hit(
+        &self,
+        ray: Ray,
+        ray_factors: Vec<f64>,
+        on_curve: impl Fn([f64; 2]) -> bool,
+    ) -> IntersectResult {
+        let mut result = IntersectResult::empty();
+        for m in ray_factors {
+            let position = [
+                ray.start_pos[0] + m * ray.vector[0],
+                ray.start_pos[1] + m * ray.vector[1],
+            ];
+            let position = match self.end_at(position) {
+                Some(end) => end,
+                None if on_curve(position) => position,
+                None => continue,
+            };
+            // skip the point the ray starts from, a curve can still be hit further along
+            if m <= 0.0 || ray.distance(position) <= EPSILON {
+                continue;
+            }
+            result.position = position;
+            result.success = true;
+            break;
+        }
+        result
+    }
+
+    fn intersect_segment(&self, ray: Ray) -> IntersectResult {
         // initialize equation variables.
         // end point 1 of mirror segment
         let a1: f64 = self.start_pos[0];
@@ -128,39 +711,277 @@ impl Mirror {
         let v1: f64 = ray.vector[0];
         let v2: f64 = ray.vector[1];
 
+        let length = ((b1 - a1).powi(2) + (b2 - a2).powi(2)).sqrt();
+        let speed = (v1 * v1 + v2 * v2).sqrt();
+        if length == 0.0 || speed == 0.0 {
+            return IntersectResult::empty();
+        }
+
         // result, will modify if successful
         let mut result = IntersectResult::empty();
-        // calculate common denominator for the fractions
+        // calculate common denominator for the fractions. divided by the speed it is the
+        // mirror length times the sine of the angle between mirror and ray
         let denominator = v2 * (b1 - a1) - v1 * (b2 - a2);
 
-        // this checks for the situation where the denominator is zero
-        // (and therefore the lines are parralel)
-        if denominator != 0.0 {
-            // calculate the factors for both vectors (in parametric line representation)
-            let m = ((b2 - a2) * (p1 - a1) - (b1 - a1) * (p2 - a2)) / denominator;
-            let n = (v2 * (p1 - a1) - v1 * (p2 - a2)) / denominator;
-            // if these factors are within the definition bounds for segment and ray
-            if 0.0 < n && 1.0 > n && m > 0.0 {
-                // set the result to intersection
-                result.position[0] = a1 + n * (b1 - a1);
-                result.position[1] = a2 + n * (b2 - a2);
+        if denominator.abs() <= EPSILON * speed {
+            // parallel. a ray running along the mirror's line towards it meets it edge on, at
+            // the nearer end. a ray already on the mirror slides along it and off the far
+            // end, otherwise it would bounce between the ends from inside the mirror. any
+            // other parallel ray passes by
+            let off_line = ((p1 - a1) * (b2 - a2) - (p2 - a2) * (b1 - a1)).abs() / length;
+            if off_line > EPSILON {
+                return result;
+            }
+            let ahead = |end: [f64; 2]| ((end[0] - p1) * v1 + (end[1] - p2) * v2) / speed;
+            let (to_start, to_end) = (ahead(self.start_pos), ahead(self.end_pos));
+            if to_start > EPSILON && to_end > EPSILON {
+                result.position = if to_start < to_end {
+                    self.start_pos
+                } else {
+                    self.end_pos
+                };
                 result.success = true;
             }
+            return result;
+        }
+
+        // calculate the factors for both vectors (in parametric line representation)
+        let m = ((b2 - a2) * (p1 - a1) - (b1 - a1) * (p2 - a2)) / denominator;
+        let n = (v2 * (p1 - a1) - v1 * (p2 - a2)) / denominator;
+        // the ends belong to the mirror, a little past them still counts as on them
+        if m > 0.0 && n * length >= -EPSILON && (n - 1.0) * length <= EPSILON {
+            result.position = if n * length <= EPSILON {
+                self.start_pos
+            } else if (1.0 - n) * length <= EPSILON {
+                self.end_pos
+            } else {
+                [a1 + n * (b1 - a1), a2 + n * (b2 - a2)]
+            };
+            result.success = true;
         }
-        // since the result will be a fail unless specified above, we are clear to return here only
         result
     }
 
-    fn normal(&self) -> [f64; 2] {
-        let mirror_vec =  [ 
-            self.end_pos[0] - self.start_pos[0],
-            self.end_pos[1] - self.start_pos[1]
-        ];
-        [
-            mirror_vec[1],
-            -1f64 * mirror_vec[0],
-        ]
-        
+    // normal of the mirror at a point on it. for curves it points away from the
+    // center of the circle or away from the focus side of the parabola
+    fn normal(&self, position: [f64; 2]) -> [f64; 2] {
+        match self.shape {
+            Shape::Segment => {
+                let mirror_vec = [
+                    self.end_pos[0] - self.start_pos[0],
+                    self.end_pos[1] - self.start_pos[1],
+                ];
+                [mirror_vec[1], -mirror_vec[0]]
+            }
+            Shape::Arc { center, .. } => [position[0] - center[0], position[1] - center[1]],
+            Shape::Parabola {
+                vertex,
+                axis,
+                focal_length,
+                ..
+            } => {
+                // gradient of u^2 - 4 f v, turned back into window coordinates
+                let across = [axis[1], -axis[0]];
+                let u =
+                    (position[0] - vertex[0]) * across[0] + (position[1] - vertex[1]) * across[1];
+                [
+                    2f64 * u * across[0] - 4f64 * focal_length * axis[0],
+                    2f64 * u * across[1] - 4f64 * focal_length * axis[1],
+                ]
+            }
+        }
+    }
+
+    // true if normal() points to the left of the way from start_pos to end_pos on screen.
+    // y points down, so left of a mirror running to the right is up
+    fn normal_is_left(&self) -> bool {
+        match self.shape {
+            Shape::Segment => true,
+            // arcs with a positive sweep run clockwise on screen, with the center on the right
+            Shape::Arc { sweep, .. } => sweep >= 0.0,
+            Shape::Parabola { vertex, axis, .. } => {
+                let u_of = |point: [f64; 2]| {
+                    (point[0] - vertex[0]) * axis[1] - (point[1] - vertex[1]) * axis[0]
+                };
+                u_of(self.end_pos) >= u_of(self.start_pos)
+            }
+        }
+    }
+
+    // normal() turned to point out of the front of a one sided mirror
+    pub fn front_normal(&self, position: [f64; 2]) -> [f64; 2] {
+        let normal = self.normal(position);
+        match self.sides {
+            Sides::One { front, .. } if (front == io::Side::Left) != self.normal_is_left() => {
+                [-normal[0], -normal[1]]
+            }
+            _ => normal,
+        }
+    }
+
+    // true if a ray arriving against `normal` (see surface_normal) reaches a side that
+    // works. glass works from both sides either way
+    fn faces(&self, ray: &Ray, normal: [f64; 2]) -> bool {
+        match (self.sides, self.surface) {
+            (Sides::Both, _) | (_, Surface::Dielectric { .. }) => true,
+            (Sides::One { .. }, _) => ray.vector[0] * normal[0] + ray.vector[1] * normal[1] < 0.0,
+        }
+    }
+
+    // short lines on the back of a one sided mirror, like the hatching on the back of a
+    // mirror in an optics drawing. none for mirrors that work from both sides
+    pub fn back_ticks(&self) -> Vec<[f64; 4]> {
+        if self.sides == Sides::Both {
+            return vec![];
+        }
+        let mut ticks = Vec::new();
+        // distance to the next tick, carried over from one outline piece to the next
+        let mut to_next = TICK_SPACING / 2f64;
+        for [x1, y1, x2, y2] in self.outline() {
+            let length = (x2 - x1).hypot(y2 - y1);
+            let mut along = to_next;
+            while along <= length {
+                let point = [
+                    x1 + (x2 - x1) * along / length,
+                    y1 + (y2 - y1) * along / length,
+                ];
+                let front = paths::unit(self.front_normal(point));
+                ticks.push([
+                    point[0],
+                    point[1],
+                    point[0] - front[0] * TICK_LENGTH,
+                    point[1] - front[1] * TICK_LENGTH,
+                ]);
+                along += TICK_SPACING;
+            }
+            to_next = along - length;
+        }
+        ticks
+    }
+
+    // true if `position` is on one of the ends of the mirror
+    fn has_end(&self, position: [f64; 2]) -> bool {
+        self.end_at(position).is_some()
+    }
+
+    // the end of the mirror within EPSILON of `position`, if there is one
+    fn end_at(&self, position: [f64; 2]) -> Option<[f64; 2]> {
+        [self.start_pos, self.end_pos].into_iter().find(|end| {
+            (end[0] - position[0]).powi(2) + (end[1] - position[1]).powi(2) <= EPSILON * EPSILON
+        })
+    }
+
+    // push the rays that leave this surface after `ray` hits it at `position`, where the
+    // surface faces along `normal` (see surface_normal). rough surfaces draw their random
+    // directions from `seed`
+    fn interact(
+        &self,
+        ray: &Ray,
+        position: [f64; 2],
+        normal: [f64; 2],
+        seed: u64,
+        outgoing: &mut Vec<Ray>,
+    ) {
+        if !self.faces(ray, normal) {
+            // the back of a one sided mirror
+            if let Sides::One {
+                back: io::BackFace::Pass,
+                ..
+            } = self.sides
+            {
+                outgoing.push(Ray {
+                    start_pos: position,
+                    bounces: ray.bounces + 1,
+                    ..*ray
+                });
+            }
+            return;
+        }
+        match self.surface {
+            Surface::Mirror => {
+                outgoing.push(ray.reflect(position, normal, self.absorption_factor));
+            }
+            // the tracer records the hit, nothing leaves
+            Surface::Detector { .. } => {}
+            Surface::Diffuse { samples } => {
+                let facing = facing_normal(ray, normal);
+                let energy = ray.energy * (1f64 - self.absorption_factor) / samples as f64;
+                let mut rng = StdRng::seed_from_u64(hit_seed(seed, ray, position));
+                for _ in 0..samples {
+                    // lambert's law in 2d: brightness goes with the cosine of the angle to
+                    // the normal, so sin(angle) is uniform
+                    let angle = (rng.random_range(-1f64..=1f64)).asin();
+                    outgoing.push(ray.scatter(position, rotate(facing, angle), energy));
+                }
+            }
+            Surface::Glossy { shininess, samples } => {
+                let facing = facing_normal(ray, normal);
+                let mirrored = paths::unit(ray.reflect(position, normal, 0.0).vector);
+                let energy = ray.energy * (1f64 - self.absorption_factor) / samples as f64;
+                let mut rng = StdRng::seed_from_u64(hit_seed(seed, ray, position));
+                // past the angle where cos^shininess drops below 1e-9 there is nothing worth
+                // sampling, so only draw from inside it
+                let widest = GLOSSY_CUTOFF
+                    .powf(1f64 / shininess)
+                    .acos()
+                    .min(std::f64::consts::FRAC_PI_2);
+                for _ in 0..samples {
+                    // phong in 2d: angles around the mirror direction with a density of
+                    // cos(angle)^shininess, by rejection
+                    let angle = loop {
+                        let angle = rng.random_range(-widest..=widest);
+                        if rng.random::<f64>() <= angle.cos().powf(shininess) {
+                            break angle;
+                        }
+                    };
+                    let mut direction = rotate(mirrored, angle);
+                    // the part of the lobe that dips into the surface gets folded back out
+                    let into = direction[0] * facing[0] + direction[1] * facing[1];
+                    if into < 0.0 {
+                        direction = [
+                            direction[0] - 2f64 * into * facing[0],
+                            direction[1] - 2f64 * into * facing[1],
+                        ];
+                    }
+                    outgoing.push(ray.scatter(position, direction, energy));
+                }
+            }
+            Surface::Splitter { reflectance } => {
+                let energy = ray.energy * (1f64 - self.absorption_factor);
+                let reflected = Ray {
+                    energy: energy * reflectance,
+                    ..ray.reflect(position, normal, 0.0)
+                };
+                let transmitted = ray.scatter(position, ray.vector, energy * (1f64 - reflectance));
+                // a splitter set to 0 or 1 is just glass or a mirror, no need for an empty ray
+                outgoing.extend(
+                    [reflected, transmitted]
+                        .into_iter()
+                        .filter(|ray| ray.energy > 0.0),
+                );
+            }
+            Surface::Dielectric {
+                refractive_index_front,
+                refractive_index_back,
+            } => {
+                // the ray comes from the front if it travels against the normal
+                let from_front = ray.vector[0] * normal[0] + ray.vector[1] * normal[1] < 0.0;
+                let (n1, n2) = if from_front {
+                    (refractive_index_front, refractive_index_back)
+                } else {
+                    (refractive_index_back, refractive_index_front)
+                };
+                // dispersion: every wavelength sees its own index
+                let wavelength = ray.wavelength.unwrap_or(REFERENCE_WAVELENGTH);
+                let (n1, n2) = (n1.at(wavelength), n2.at(wavelength));
+                let (reflected, refracted) =
+                    ray.refract(position, normal, n1, n2, self.absorption_factor);
+                outgoing.push(reflected);
+                if let Some(refracted) = refracted {
+                    outgoing.push(refracted);
+                }
+            }
+        }
     }
 }
 
@@ -175,7 +996,7 @@ pub fn generate_mirrors(mirror_count: i32) -> Vec<Mirror> {
     let mut mirrors: Vec<Mirror> = Vec::new();
 
     let mut rng = rand::rng();
-    for n in 0..mirror_count {
+    for _ in 0..mirror_count {
         let start_pos = [
             rng.random_range(min_rand..=max_rand) as f64,
             rng.random_range(min_rand..=max_rand) as f64,
@@ -189,69 +1010,117 @@ pub fn generate_mirrors(mirror_count: i32) -> Vec<Mirror> {
             start_pos,
             end_pos,
             color: white,
+            absorption_factor: 0.0,
+            surface: Surface::Mirror,
+            shape: Shape::Segment,
+            sides: Sides::Both,
         })
     }
 
     mirrors
 }
 
-pub fn generate_mirrors_json(path: &str) -> Vec<Mirror> {
-    // for now this only accepts pixel dimensions
-    let mut mirrors:Vec<Mirror> = Vec::new();
-    
-    let json_data = io::read_json(path);
-    
-    let coord_format = "pixels";
-    
-    let mirrors_from_json = &json_data.mirrors;
-    
-    mirrors = mirrors_from_json.iter()
-        .map(|mirror| Mirror {
-            start_pos: [mirror.start_pos[0], mirror.start_pos[1]],
-            end_pos: [mirror.end_pos[0], mirror.end_pos[1]],
-            color: [1.0;4]
-        }).collect();
-        
-    
-    mirrors
+pub fn generate_mirrors_json(
+    path: &str,
+    window_dimensions: [f64; 2],
+) -> Result<Vec<Mirror>, io::SceneError> {
+    let json_data = io::read_json(path)?;
+    mirrors_from_json(&json_data, window_dimensions)
 }
-/*
 
-fn generate_mirrors(path: &str, window_dimensions: [f64; 2]) -> Vec<Mirror> {
+// resolve the mirrors of an already loaded scene against the window size.
+// call this again whenever the window is resized
+pub fn mirrors_from_json(
+    json_data: &io::JSON,
+    window_dimensions: [f64; 2],
+) -> Result<Vec<Mirror>, io::SceneError> {
+    // refuse the scene on the first problem, `io::validate` lists all of them
+    if let Some(problem) = io::validate(json_data).into_iter().next() {
+        return Err(problem);
+    }
+    let coord_format: CoordFormat = json_data.coord_format.parse()?;
+
     let mut mirrors: Vec<Mirror> = Vec::new();
+    for (index, mirror) in json_data.mirrors.iter().enumerate() {
+        let resolve = |values: &Vec<f64>, field: &'static str| {
+            Ok::<[f64; 2], io::SceneError>(
+                coord_format.resolve(io::point(values, index, field)?, window_dimensions),
+            )
+        };
+        let start_pos = resolve(&mirror.start_pos, "start_pos")?;
+        let end_pos = resolve(&mirror.end_pos, "end_pos")?;
+        let color = match mirror.surface {
+            io::SurfaceKind::Mirror => [1.0; 4],
+            // glass is drawn light blue so it stands out from the mirrors
+            io::SurfaceKind::Dielectric => [0.6, 0.8, 1.0, 1.0],
+            io::SurfaceKind::Detector => [0.2, 1.0, 0.4, 1.0],
+            // rough walls in grey, polished ones a bit brighter
+            io::SurfaceKind::Diffuse => [0.55, 0.55, 0.55, 1.0],
+            io::SurfaceKind::Glossy => [0.8, 0.8, 0.85, 1.0],
+            io::SurfaceKind::Splitter => [0.9, 0.7, 1.0, 1.0],
+        };
+        let surface = match mirror.surface {
+            io::SurfaceKind::Mirror => Surface::Mirror,
+            io::SurfaceKind::Dielectric => Surface::Dielectric {
+                refractive_index_front: mirror.refractive_index_front,
+                refractive_index_back: mirror.refractive_index_back,
+            },
+            io::SurfaceKind::Detector => Surface::Detector {
+                bins: mirror.bins.unwrap_or(detector::DEFAULT_BINS),
+            },
+            io::SurfaceKind::Diffuse => Surface::Diffuse {
+                samples: mirror.samples.unwrap_or(DEFAULT_SAMPLES),
+            },
+            io::SurfaceKind::Glossy => Surface::Glossy {
+                shininess: mirror.shininess.unwrap_or(DEFAULT_SHININESS),
+                samples: mirror.samples.unwrap_or(DEFAULT_SAMPLES),
+            },
+            io::SurfaceKind::Splitter => Surface::Splitter {
+                reflectance: mirror.reflectance.unwrap_or(DEFAULT_REFLECTANCE),
+            },
+        };
+        // curved shapes need extra points, complain about the first one missing
+        let required = |values: &Option<Vec<f64>>, field: &'static str| match values {
+            Some(values) => resolve(values, field),
+            None => Err(io::SceneError::MissingField {
+                mirror: index,
+                field,
+            }),
+        };
 
-    let json_data = io::read_json(path);
-    let coord_format = &json_data.coord_format;
-    let json_mirrors = &json_data.mirrors;
-    if coord_format == "pixels" {
-        mirrors = json_mirrors
-            .iter()
-            .map(|mirror| Mirror {
-                start_pos: physics::Vector2::new(mirror.start_pos[0], mirror.start_pos[1]),
-                end_pos: physics::Vector2::new(mirror.end_pos[0], mirror.end_pos[1]),
-                absorption_factor: mirror.absorption_factor as f32,
-            })
-            .collect();
-    } else if coord_format == "fractions" {
-        mirrors = json_mirrors
-            .iter()
-            .map(|mirror| Mirror {
-                start_pos: physics::Vector2::new(
-                    mirror.start_pos[0] * window_dimensions[0],
-                    mirror.start_pos[1] * window_dimensions[1],
-                ),
-                end_pos: physics::Vector2::new(
-                    mirror.end_pos[0] * window_dimensions[0],
-                    mirror.end_pos[1] * window_dimensions[1],
-                ),
-                absorption_factor: mirror.absorption_factor as f32,
-            })
-            .collect();
-    } else {
-        panic!("wrong coord type in the file at: {:}\nexiting...", path)
+        let mut built = match mirror.shape {
+            io::ShapeKind::Segment => {
+                Mirror::segment(start_pos, end_pos, color, mirror.absorption_factor, surface)
+            }
+            io::ShapeKind::Arc => Mirror::arc(
+                start_pos,
+                required(&mirror.through_pos, "through_pos")?,
+                end_pos,
+                color,
+                mirror.absorption_factor,
+                surface,
+            ),
+            io::ShapeKind::Parabola => Mirror::parabola(
+                required(&mirror.vertex, "vertex")?,
+                required(&mirror.focus, "focus")?,
+                start_pos,
+                end_pos,
+                color,
+                mirror.absorption_factor,
+                surface,
+            ),
+        };
+        if let Some(front) = mirror.front {
+            built.sides = Sides::One {
+                front,
+                back: mirror.back,
+            };
+        }
+        mirrors.push(built);
     }
 
-*/
+    Ok(mirrors)
+}
 
 pub fn generate_rays(ray_count: f64, start: [f64; 2]) -> Vec<Ray> {
     // color of the ray. for now yellow
@@ -264,7 +1133,7 @@ pub fn generate_rays(ray_count: f64, start: [f64; 2]) -> Vec<Ray> {
     let pi = std::f64::consts::PI;
 
     // iter through the ray count to make rays
-    for mut n in 0..ray_count as i32 {
+    for n in 0..ray_count as i32 {
         // angle: use fraction of 2pi
         let m = n as f64;
         let angle = 2f64 * pi * m / ray_count;
@@ -275,6 +1144,10 @@ pub fn generate_rays(ray_count: f64, start: [f64; 2]) -> Vec<Ray> {
             start_pos: start,
             vector,
             color: yellow,
+            energy: 1.0,
+            wavelength: None,
+            bounces: 0,
+            id: n as usize,
         };
         // add ray to vec
         result.push(ray);
@@ -322,46 +1195,192 @@ pub fn generate_rays(ray_count: f64, start: [f64; 2]) -> Vec<Ray> {
     [ray.start_pos, closest_position]
 }
 */
+// unit normal on the side `ray` comes from
+fn facing_normal(ray: &Ray, normal: [f64; 2]) -> [f64; 2] {
+    let normal = paths::unit(normal);
+    if ray.vector[0] * normal[0] + ray.vector[1] * normal[1] > 0.0 {
+        [-normal[0], -normal[1]]
+    } else {
+        normal
+    }
+}
+
+// the normal `ray` sees where it hits `mirrors[index]` at `position`. on an end other
+// mirrors share, like the corners of a box, it is the average of the unit normals of all
+// mirrors meeting there, each turned to face into the corner. that mirrors the ray on the
+// corner's bisector, so it bounces back into the corner instead of slipping out through
+// the joint. a ray running along a lone mirror meets its edge, which faces straight back
+pub fn surface_normal(mirrors: &[Mirror], index: usize, ray: &Ray, position: [f64; 2]) -> [f64; 2] {
+    let mirror = &mirrors[index];
+    let own = mirror.front_normal(position);
+    if !mirror.has_end(position) {
+        return own;
+    }
+    let direction = paths::unit(ray.vector);
+    let corner: Vec<&Mirror> = mirrors
+        .iter()
+        .filter(|other| other.has_end(position))
+        .collect();
+    // from the corner towards the far ends of the mirrors meeting there
+    let inward = corner.iter().fold([0.0, 0.0], |sum, other| {
+        let distance = |end: [f64; 2]| (end[0] - position[0]).hypot(end[1] - position[1]);
+        let far = if distance(other.start_pos) < distance(other.end_pos) {
+            other.end_pos
+        } else {
+            other.start_pos
+        };
+        let out = paths::unit([far[0] - position[0], far[1] - position[1]]);
+        [sum[0] + out[0], sum[1] + out[1]]
+    });
+    let mut sum = [0.0, 0.0];
+    for other in corner {
+        let normal = paths::unit(other.normal(position));
+        let cos = normal[0] * direction[0] + normal[1] * direction[1];
+        // the side the ray comes from, or for a mirror the ray runs along, the side the
+        // other mirrors are on
+        let side = if cos.abs() > EPSILON {
+            -cos
+        } else {
+            normal[0] * inward[0] + normal[1] * inward[1]
+        };
+        let facing = if side.abs() <= EPSILON {
+            [-direction[0], -direction[1]]
+        } else if side < 0.0 {
+            [-normal[0], -normal[1]]
+        } else {
+            normal
+        };
+        sum = [sum[0] + facing[0], sum[1] + facing[1]];
+    }
+    // point it to the side the hit mirror calls its front, glass needs that to tell which
+    // way the ray crosses
+    if sum[0] * own[0] + sum[1] * own[1] < 0.0 {
+        [-sum[0], -sum[1]]
+    } else {
+        sum
+    }
+}
+
+// the closest mirror a ray runs into
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hit {
+    // index into the mirror vec
+    pub mirror: usize,
+    pub position: [f64; 2],
+    pub distance: f64,
+}
+
+// check intersections for every mirror, for 1 ray. the bvh gives the same answer a lot
+// faster, this stays around as the reference it gets checked against
+pub fn closest_hit_brute_force(ray: &Ray, mirrors: &[Mirror]) -> Option<Hit> {
+    // distance will track the distance value of the closest mirror so far
+    let mut closest: Option<Hit> = None;
+
+    for (index, mirror) in mirrors.iter().enumerate() {
+        if let Some((position, distance)) = mirror.hit(ray) {
+            // if the current intersection is the closest so far, store all the interesting info.
+            // on a tie the first mirror wins
+            if closest.is_none_or(|hit| distance < hit.distance) {
+                closest = Some(Hit {
+                    mirror: index,
+                    position,
+                    distance,
+                });
+            }
+        }
+    }
+    closest
+}
+
+// trace one bounce for every ray. a hit can spawn more than one outgoing ray
+// (glass both reflects and refracts, rough surfaces scatter several samples). outgoing
+// rays whose energy drops below energy_threshold are terminated instead of being passed
+// on, and past max_rays outgoing rays the weakest ones are dropped, see prune_weakest.
+// record_paths fills in the path events, which costs memory on big traces. seed
+// decides where rough surfaces scatter to, the same seed scatters the same way
 pub fn find_closest_mirror_reflections(
-    rays: &Vec<Ray>,
-    mirrors: &Vec<Mirror>,
+    rays: &[Ray],
+    mirrors: &[Mirror],
+    bvh: &MirrorBvh,
+    energy_threshold: f64,
+    max_rays: usize,
+    record_paths: bool,
+    seed: u64,
+) -> ReflectionHandlerResult {
+    reflect_rays(rays, mirrors, bvh, energy_threshold, max_rays, record_paths, seed).finish()
+}
+
+// find_closest_mirror_reflections without adding up the pruned energy
+fn reflect_rays(
+    rays: &[Ray],
+    mirrors: &[Mirror],
+    bvh: &MirrorBvh,
+    energy_threshold: f64,
+    max_rays: usize,
+    record_paths: bool,
+    seed: u64,
 ) -> ReflectionHandlerResult {
     let mut result: ReflectionHandlerResult = ReflectionHandlerResult::empty();
 
     for ray in rays {
-        // initialize tracking variables
-        // distance will track the distance value of the closest mirror so far
-        let mut distance = f64::MAX;
-        // closest position is the closest position
-        let mut closest_position: [f64; 2] = [f64::MAX; 2];
-        let mut _mirror = Mirror::empty();
-
-        for mirror in mirrors {
-            let intersect = mirror.intersect(ray.clone());
-            if intersect.success {
-                let position = intersect.position;
-                let current_distance = ray.distance(position);
-                if current_distance < distance && current_distance > 10f64.powi(-10) {
-                    // if the current intersection is the closest so far,
-                    // store all the interesting info
-                    distance = current_distance;
-                    closest_position = position;
-                    _mirror = mirror.clone();
-                }
-            }
-        }
-
-        if closest_position[0] < f64::MAX && closest_position[1] < f64::MAX {
+        if let Some(hit) = bvh.closest_hit(ray, mirrors) {
+            let closest_position = hit.position;
+            let _mirror = mirrors[hit.mirror];
             let draw_line = [
                 ray.start_pos[0],
                 ray.start_pos[1],
                 closest_position[0],
                 closest_position[1],
             ];
-            result.draw_line.push(draw_line);
-            // FIXME the normal vector is wrong for some reason
-            let new_ray = ray.reflect(closest_position, _mirror.normal());
-            result.reflected_rays.push(new_ray);
+            result.draw_line.push(Segment {
+                coords: draw_line,
+                energy: ray.energy,
+                wavelength: ray.wavelength,
+            });
+            let normal = surface_normal(mirrors, hit.mirror, ray, closest_position);
+            // one sided detectors only count what arrives on their front
+            if let Surface::Detector { .. } = _mirror.surface {
+                if _mirror.faces(ray, normal) {
+                    result.detector_hits.push(DetectorHit::new(
+                        hit.mirror,
+                        &_mirror,
+                        ray,
+                        closest_position,
+                    ));
+                }
+            }
+            let mut outgoing: Vec<Ray> = Vec::with_capacity(2);
+            _mirror.interact(ray, closest_position, normal, seed, &mut outgoing);
+            if record_paths {
+                let event = |leaving: Option<&Ray>| PathEvent {
+                    ray: ray.id,
+                    bounce: ray.bounces,
+                    mirror: hit.mirror,
+                    position: closest_position,
+                    incident: paths::unit(ray.vector),
+                    energy: ray.energy,
+                    outgoing: leaving.map(|new_ray| paths::unit(new_ray.vector)),
+                    outgoing_energy: leaving.map_or(0.0, |new_ray| new_ray.energy),
+                    wavelength: ray.wavelength,
+                };
+                if outgoing.is_empty() {
+                    result.path_events.push(event(None));
+                }
+                result
+                    .path_events
+                    .extend(outgoing.iter().map(|new_ray| event(Some(new_ray))));
+            }
+            // only keep the children that are bright enough to be worth tracing
+            result.reflected_rays.extend(
+                outgoing
+                    .into_iter()
+                    .filter(|new_ray| new_ray.energy >= energy_threshold),
+            );
+            // keep the generation from outgrowing the cap while it is being built. waiting
+            // until it holds twice the cap means every ray gets sorted only a few times
+            if result.reflected_rays.len() > max_rays.saturating_mul(2) {
+                result.prune(max_rays);
+            }
             // println!(
             //     "closest_position: {:?} ray: {:?}, mirror : {:?}, normalVector: {:?} -> {:?} ", 
             //     closest_position, 
@@ -374,5 +1393,875 @@ pub fn find_closest_mirror_reflections(
         }
     }
     //println!("\n\n\n");
+    result.prune(max_rays);
     result
 }
+
+// rays per work item for the parallel tracer. small enough to balance the threads,
+// big enough that the bookkeeping does not show up
+const PARALLEL_CHUNK_SIZE: usize = 256;
+
+// same as find_closest_mirror_reflections, but with the rays spread over rayon's thread pool.
+// every chunk is traced serially and the chunks are put back together in order, pruned
+// to max_rays at every join, so the rays that come out are exactly the ones the serial
+// version gives, and so is the pruned energy, see ReflectionHandlerResult::finish
+pub fn find_closest_mirror_reflections_parallel(
+    rays: &[Ray],
+    mirrors: &[Mirror],
+    bvh: &MirrorBvh,
+    energy_threshold: f64,
+    max_rays: usize,
+    record_paths: bool,
+    seed: u64,
+) -> ReflectionHandlerResult {
+    rays.par_chunks(PARALLEL_CHUNK_SIZE)
+        .map(|chunk| {
+            reflect_rays(
+                chunk,
+                mirrors,
+                bvh,
+                energy_threshold,
+                max_rays,
+                record_paths,
+                seed,
+            )
+        })
+        .reduce(ReflectionHandlerResult::empty, |mut result, chunk| {
+            result.draw_line.extend(chunk.draw_line);
+            result.reflected_rays.extend(chunk.reflected_rays);
+            result.detector_hits.extend(chunk.detector_hits);
+            result.path_events.extend(chunk.path_events);
+            result.pruned_rays += chunk.pruned_rays;
+            result.pruned_energies.extend(chunk.pruned_energies);
+            result.prune(max_rays);
+            result
+        })
+        .finish()
+}
+
+// drop the outgoing rays with the least energy until at most `max_rays` are left. the
+// rest keep their order, and of equally bright rays the later ones go first. that picks
+// the same rays however often it runs on the way, so pruning while a generation is built
+// gives the same rays as pruning it once at the end. returns the energies of the dropped rays
+fn prune_weakest(rays: &mut Vec<Ray>, max_rays: usize) -> Vec<f64> {
+    if rays.len() <= max_rays {
+        return vec![];
+    }
+    let mut brightest: Vec<usize> = (0..rays.len()).collect();
+    // stable, so ties stay in ray order
+    brightest.sort_by(|&a, &b| rays[b].energy.total_cmp(&rays[a].energy));
+    let mut keep = vec![false; rays.len()];
+    for &index in &brightest[..max_rays] {
+        keep[index] = true;
+    }
+
+    let mut dropped = Vec::with_capacity(rays.len() - max_rays);
+    let mut keep = keep.into_iter();
+    rays.retain(|ray| {
+        let kept = keep.next().unwrap();
+        if !kept {
+            dropped.push(ray.energy);
+        }
+        kept
+    });
+    dropped
+}
+
+// size the thread pool used by the parallel tracer. 0 means one thread per core.
+// only the first call has an effect, the pool cannot be resized once it is running
+pub fn set_thread_count(thread_count: usize) {
+    if let Err(err) = rayon::ThreadPoolBuilder::new()
+        .num_threads(thread_count)
+        .build_global()
+    {
+        eprintln!("could not set the thread count: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::Dispersion;
+    use rand::rngs::StdRng;
+
+    // two facing mirrors that absorb half of the incoming light
+    fn half_absorbing_corridor() -> Vec<Mirror> {
+        vec![
+            Mirror {
+                start_pos: [0.0, -10.0],
+                end_pos: [0.0, 10.0],
+                color: [1.0; 4],
+                absorption_factor: 0.5,
+                surface: Surface::Mirror,
+                shape: Shape::Segment,
+                sides: Sides::Both,
+            },
+            Mirror {
+                start_pos: [100.0, -10.0],
+                end_pos: [100.0, 10.0],
+                color: [1.0; 4],
+                absorption_factor: 0.5,
+                surface: Surface::Mirror,
+                shape: Shape::Segment,
+                sides: Sides::Both,
+            },
+        ]
+    }
+
+    #[test]
+    fn reflection_scales_energy_by_absorption() {
+        let ray = Ray::new([50.0, 0.0], [1.0, 0.0], [1.0; 4]);
+        let reflected = ray.reflect([100.0, 0.0], [1.0, 0.0], 0.25);
+        assert_eq!(reflected.energy, 0.75);
+        assert_eq!(reflected.vector, [-1.0, 0.0]);
+    }
+
+    #[test]
+    fn half_absorbing_chain_halves_energy_per_bounce() {
+        let mirrors = half_absorbing_corridor();
+        let mut rays = vec![Ray::new([50.0, 0.0], [1.0, 0.0], [1.0; 4])];
+
+        let mut expected = 1.0;
+        for _ in 0..6 {
+            let result = find_closest_mirror_reflections(
+                &rays,
+                &mirrors,
+                &MirrorBvh::build(&mirrors),
+                0.0,
+                usize::MAX,
+                false,
+                0,
+            );
+            assert_eq!(result.draw_line.len(), 1);
+            assert_eq!(result.draw_line[0].energy, expected);
+
+            expected *= 0.5;
+            assert_eq!(result.reflected_rays.len(), 1);
+            assert_eq!(result.reflected_rays[0].energy, expected);
+            rays = result.reflected_rays;
+        }
+    }
+
+    #[test]
+    fn rays_below_threshold_terminate() {
+        let mirrors = half_absorbing_corridor();
+        let mut rays = vec![Ray::new([50.0, 0.0], [1.0, 0.0], [1.0; 4])];
+
+        // 1 -> 0.5 -> 0.25 -> 0.125, which is below the threshold
+        let bvh = MirrorBvh::build(&mirrors);
+        let mut bounces = 0;
+        while !rays.is_empty() {
+            rays =
+                find_closest_mirror_reflections(&rays, &mirrors, &bvh, 0.2, usize::MAX, false, 0)
+                    .reflected_rays;
+            bounces += 1;
+        }
+        assert_eq!(bounces, 3);
+    }
+
+    // flat boundary along the x axis, air on the front (y < 0) and glass behind it
+    fn glass_boundary() -> Mirror {
+        Mirror {
+            start_pos: [-10.0, 0.0],
+            end_pos: [10.0, 0.0],
+            color: [1.0; 4],
+            absorption_factor: 0.0,
+            surface: Surface::Dielectric {
+                refractive_index_front: RefractiveIndex::Constant(1.0),
+                refractive_index_back: RefractiveIndex::Constant(1.5),
+            },
+            shape: Shape::Segment,
+            sides: Sides::Both,
+        }
+    }
+
+    #[test]
+    fn dielectric_follows_snell_and_conserves_energy() {
+        let angle = std::f64::consts::FRAC_PI_4;
+        let ray = Ray::new([0.0, -1.0], [angle.sin(), angle.cos()], [1.0; 4]);
+        let mut outgoing = Vec::new();
+        glass_boundary().interact(&ray, [0.0, 0.0], [0.0, -1.0], 0, &mut outgoing);
+
+        assert_eq!(outgoing.len(), 2);
+        let (reflected, refracted) = (outgoing[0], outgoing[1]);
+        // the reflected ray heads back into the air, the refracted one into the glass
+        assert!(reflected.vector[1] < 0.0);
+        assert!(refracted.vector[1] > 0.0);
+        let refracted_length = (refracted.vector[0].powi(2) + refracted.vector[1].powi(2)).sqrt();
+        let sin_t = refracted.vector[0] / refracted_length;
+        assert!((sin_t - angle.sin() / 1.5).abs() < 1e-12);
+        assert!((reflected.energy + refracted.energy - 1.0).abs() < 1e-12);
+        assert!(reflected.energy > 0.0 && reflected.energy < 0.1);
+    }
+
+    #[test]
+    fn dielectric_total_internal_reflection() {
+        // 60 degrees from inside the glass is past the critical angle of ~41.8 degrees
+        let angle = std::f64::consts::FRAC_PI_3;
+        let ray = Ray::new([0.0, 1.0], [angle.sin(), -angle.cos()], [1.0; 4]);
+        let mut outgoing = Vec::new();
+        glass_boundary().interact(&ray, [0.0, 0.0], [0.0, -1.0], 0, &mut outgoing);
+
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].energy, 1.0);
+        assert!(outgoing[0].vector[1] > 0.0);
+    }
+
+    #[test]
+    fn dispersive_glass_bends_violet_more_than_red() {
+        let mut boundary = glass_boundary();
+        boundary.surface = Surface::Dielectric {
+            refractive_index_front: RefractiveIndex::Constant(1.0),
+            refractive_index_back: RefractiveIndex::Dispersive(Dispersion::Cauchy {
+                a: 1.5,
+                b: 0.01,
+                c: 0.0,
+            }),
+        };
+        let angle = std::f64::consts::FRAC_PI_4;
+        // sine of the angle the refracted ray leaves the boundary at
+        let sin_t = |wavelength: f64| {
+            let ray = Ray {
+                wavelength: Some(wavelength),
+                ..Ray::new([0.0, -1.0], [angle.sin(), angle.cos()], [1.0; 4])
+            };
+            let mut outgoing = Vec::new();
+            boundary.interact(&ray, [0.0, 0.0], [0.0, -1.0], 0, &mut outgoing);
+            let refracted = outgoing[1];
+            assert_eq!(refracted.wavelength, Some(wavelength));
+            refracted.vector[0] / (refracted.vector[0].powi(2) + refracted.vector[1].powi(2)).sqrt()
+        };
+        let (violet, red) = (sin_t(420.0), sin_t(680.0));
+        assert!(violet < red, "violet {} red {}", violet, red);
+        let n_violet = 1.5 + 0.01 / 0.42f64.powi(2);
+        assert!((violet - angle.sin() / n_violet).abs() < 1e-12);
+    }
+
+    #[test]
+    fn parallel_tracing_matches_serial() {
+        let mirrors = mirrors_from_json(
+            &io::read_json("assets/mirrors.json").unwrap(),
+            [1920.0, 1200.0],
+        )
+        .unwrap();
+        let bvh = MirrorBvh::build(&mirrors);
+        let mut serial = generate_rays(3000.0, [900.0, 500.0]);
+        let mut parallel = serial.clone();
+
+        for _ in 0..10 {
+            let serial_result = find_closest_mirror_reflections(
+                &serial,
+                &mirrors,
+                &bvh,
+                0.01,
+                usize::MAX,
+                false,
+                0,
+            );
+            let parallel_result = find_closest_mirror_reflections_parallel(
+                &parallel,
+                &mirrors,
+                &bvh,
+                0.01,
+                usize::MAX,
+                false,
+                0,
+            );
+            assert_eq!(serial_result, parallel_result);
+            serial = serial_result.reflected_rays;
+            parallel = parallel_result.reflected_rays;
+        }
+    }
+
+    #[test]
+    fn parabola_focuses_rays_parallel_to_its_axis() {
+        // opens upwards (towards +y) with the focus 50 above the vertex
+        let mirrors = vec![Mirror::parabola(
+            [0.0, 0.0],
+            [0.0, 50.0],
+            [-80.0, 0.0],
+            [80.0, 0.0],
+            [1.0; 4],
+            0.0,
+            Surface::Mirror,
+        )];
+        let rays: Vec<Ray> = (-7..=7)
+            .map(|n| Ray::new([n as f64 * 10.0, 500.0], [0.0, -1.0], [1.0; 4]))
+            .collect();
+
+        let result = find_closest_mirror_reflections(
+            &rays,
+            &mirrors,
+            &MirrorBvh::build(&mirrors),
+            0.0,
+            usize::MAX,
+            false,
+            0,
+        );
+        assert_eq!(result.reflected_rays.len(), rays.len());
+        for ray in result.reflected_rays {
+            // the reflected ray passes through the focus: the cross product with the
+            // direction to the focus vanishes
+            let to_focus = [0.0 - ray.start_pos[0], 50.0 - ray.start_pos[1]];
+            let cross = ray.vector[0] * to_focus[1] - ray.vector[1] * to_focus[0];
+            assert!(cross.abs() < 1e-9, "ray {:?} misses the focus", ray);
+        }
+    }
+
+    #[test]
+    fn arc_is_hit_from_inside_and_outside() {
+        // upper half of the circle of radius 10 around the origin (y > 0)
+        let arc = Mirror::arc(
+            [10.0, 0.0],
+            [0.0, 10.0],
+            [-10.0, 0.0],
+            [1.0; 4],
+            0.0,
+            Surface::Mirror,
+        );
+        let from_center = arc.intersect(Ray::new([0.0, 0.0], [0.0, 1.0], [1.0; 4]));
+        assert!(from_center.success);
+        assert!((from_center.position[1] - 10.0).abs() < 1e-9);
+
+        let from_outside = arc.intersect(Ray::new([0.0, 20.0], [0.0, -1.0], [1.0; 4]));
+        assert!(from_outside.success);
+        assert!((from_outside.position[1] - 10.0).abs() < 1e-9);
+
+        // the lower half is not part of the arc
+        let downwards = arc.intersect(Ray::new([0.0, 0.0], [0.0, -1.0], [1.0; 4]));
+        assert!(!downwards.success);
+    }
+
+    // corners of a closed polygon in order, and the point the wall from the last corner
+    // back to the first bulges out through if that wall is an arc
+    type Room = (Vec<[f64; 2]>, Option<[f64; 2]>);
+
+    // closed polygons of perfect mirrors
+    fn closed_polygons() -> Vec<Room> {
+        let rotated = |angle: f64, size: f64| -> Vec<[f64; 2]> {
+            (0..4)
+                .map(|n| {
+                    let a = angle + n as f64 * std::f64::consts::FRAC_PI_2;
+                    [300.0 + size * a.cos(), 200.0 + size * a.sin()]
+                })
+                .collect()
+        };
+        vec![
+            // the bundled scene's box
+            (
+                vec![[0.0, 0.0], [1920.0, 0.0], [1920.0, 1200.0], [0.0, 1200.0]],
+                None,
+            ),
+            (rotated(0.3, 150.0), None),
+            (vec![[0.0, 0.0], [100.0, 0.0], [50.0, 86.6]], None),
+            (
+                vec![
+                    [0.0, 0.0],
+                    [40.0, -10.0],
+                    [90.0, 20.0],
+                    [70.0, 80.0],
+                    [10.0, 60.0],
+                ],
+                None,
+            ),
+            // concave, with a notch cut into the top
+            (
+                vec![
+                    [0.0, 0.0],
+                    [100.0, 0.0],
+                    [100.0, 100.0],
+                    [50.0, 40.0],
+                    [0.0, 100.0],
+                ],
+                None,
+            ),
+            // a square with its bottom wall bulging out, meeting the others at an angle
+            (
+                vec![[0.0, 100.0], [0.0, 0.0], [100.0, 0.0], [100.0, 100.0]],
+                Some([50.0, 130.0]),
+            ),
+        ]
+    }
+
+    fn walls(corners: &[[f64; 2]], bulge: Option<[f64; 2]>) -> Vec<Mirror> {
+        (0..corners.len())
+            .map(|n| {
+                let (start, end) = (corners[n], corners[(n + 1) % corners.len()]);
+                match bulge {
+                    Some(through) if n + 1 == corners.len() => {
+                        Mirror::arc(start, through, end, [1.0; 4], 0.0, Surface::Mirror)
+                    }
+                    _ => Mirror::segment(start, end, [1.0; 4], 0.0, Surface::Mirror),
+                }
+            })
+            .collect()
+    }
+
+    // inside the polygon, or between the chord of the arc wall and the arc
+    fn inside_room(corners: &[[f64; 2]], bulge: Option<[f64; 2]>, point: [f64; 2]) -> bool {
+        if inside(corners, point) {
+            return true;
+        }
+        let Some(through) = bulge else {
+            return false;
+        };
+        let (start, end) = (corners[corners.len() - 1], corners[0]);
+        let Shape::Arc { center, radius, .. } =
+            Mirror::arc(start, through, end, [1.0; 4], 0.0, Surface::Mirror).shape
+        else {
+            unreachable!()
+        };
+        let side = |p: [f64; 2]| {
+            (end[0] - start[0]) * (p[1] - start[1]) - (end[1] - start[1]) * (p[0] - start[0])
+        };
+        (point[0] - center[0]).hypot(point[1] - center[1]) <= radius + 1e-6
+            && side(point) * side(through) >= 0.0
+    }
+
+    // even-odd rule, with points within a hair of a wall counting as inside
+    fn inside(corners: &[[f64; 2]], point: [f64; 2]) -> bool {
+        let mut crossings = 0;
+        for n in 0..corners.len() {
+            let (a, b) = (corners[n], corners[(n + 1) % corners.len()]);
+            let along = [b[0] - a[0], b[1] - a[1]];
+            let length_sq = along[0] * along[0] + along[1] * along[1];
+            let t = (((point[0] - a[0]) * along[0] + (point[1] - a[1]) * along[1]) / length_sq)
+                .clamp(0.0, 1.0);
+            let closest = [a[0] + t * along[0], a[1] + t * along[1]];
+            if (closest[0] - point[0]).hypot(closest[1] - point[1]) < 1e-6 {
+                return true;
+            }
+            if (a[1] > point[1]) != (b[1] > point[1])
+                && point[0] < a[0] + (point[1] - a[1]) / along[1] * along[0]
+            {
+                crossings += 1;
+            }
+        }
+        crossings % 2 == 1
+    }
+
+    // rays from random points inside, in random directions, aimed right at the corners,
+    // and running along the walls
+    fn rays_inside(corners: &[[f64; 2]], count: usize, rng: &mut StdRng) -> Vec<Ray> {
+        let (min, max) = corners.iter().fold(
+            ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]),
+            |(min, max), c| {
+                (
+                    [min[0].min(c[0]), min[1].min(c[1])],
+                    [max[0].max(c[0]), max[1].max(c[1])],
+                )
+            },
+        );
+        let mut rays = Vec::with_capacity(count);
+        while rays.len() < count {
+            let corner = corners[rng.random_range(0..corners.len())];
+            let next =
+                corners[(corners.iter().position(|&c| c == corner).unwrap() + 1) % corners.len()];
+            let ray = match rays.len() % 3 {
+                0 => {
+                    let start = [
+                        rng.random_range(min[0]..max[0]),
+                        rng.random_range(min[1]..max[1]),
+                    ];
+                    let angle: f64 = rng.random_range(0.0..std::f64::consts::TAU);
+                    Ray::new(start, [angle.cos(), angle.sin()], [1.0; 4])
+                }
+                1 => {
+                    let start = [
+                        rng.random_range(min[0]..max[0]),
+                        rng.random_range(min[1]..max[1]),
+                    ];
+                    Ray::new(
+                        start,
+                        [corner[0] - start[0], corner[1] - start[1]],
+                        [1.0; 4],
+                    )
+                }
+                _ => {
+                    let t: f64 = rng.random_range(0.0..1.0);
+                    let along = [next[0] - corner[0], next[1] - corner[1]];
+                    Ray::new(
+                        [corner[0] + t * along[0], corner[1] + t * along[1]],
+                        along,
+                        [1.0; 4],
+                    )
+                }
+            };
+            if inside(corners, ray.start_pos) {
+                rays.push(ray);
+            }
+        }
+        rays
+    }
+
+    // fires a million rays into closed polygons and follows each for a few bounces. none
+    // of them may find a way out, through a corner or anywhere else
+    #[test]
+    fn rays_never_escape_closed_polygons() {
+        let mut rng = StdRng::seed_from_u64(22);
+        let polygons = closed_polygons();
+        let rays_per_polygon = 1_000_000 / polygons.len();
+        for (corners, bulge) in polygons {
+            let mirrors = walls(&corners, bulge);
+            let bvh = MirrorBvh::build(&mirrors);
+            let mut rays = rays_inside(&corners, rays_per_polygon, &mut rng);
+            for bounce in 0..4 {
+                let result = find_closest_mirror_reflections_parallel(
+                    &rays,
+                    &mirrors,
+                    &bvh,
+                    0.0,
+                    usize::MAX,
+                    false,
+                    0,
+                );
+                assert_eq!(
+                    result.reflected_rays.len(),
+                    rays.len(),
+                    "rays escaped {:?} at bounce {}",
+                    corners,
+                    bounce
+                );
+                for ray in &result.reflected_rays {
+                    assert!(
+                        inside_room(&corners, bulge, ray.start_pos),
+                        "{:?} left {:?}",
+                        ray,
+                        corners
+                    );
+                }
+                rays = result.reflected_rays;
+            }
+        }
+    }
+
+    #[test]
+    fn segment_ends_belong_to_the_mirror() {
+        let mirror = Mirror::segment([0.0, 0.0], [100.0, 0.0], [1.0; 4], 0.0, Surface::Mirror);
+        for (x, hit) in [
+            (0.0, true),
+            (100.0, true),
+            (100.0 + EPSILON / 2.0, true),
+            (100.1, false),
+        ] {
+            let result = mirror.intersect(Ray::new([x, -10.0], [0.0, 1.0], [1.0; 4]));
+            assert_eq!(result.success, hit, "x = {}", x);
+        }
+        // a hit within EPSILON of an end lands exactly on it
+        let result = mirror.intersect(Ray::new([1e-8, -10.0], [0.0, 1.0], [1.0; 4]));
+        assert_eq!(result.position, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn collinear_rays_hit_the_end_ahead() {
+        let mirrors = vec![Mirror::segment(
+            [0.0, 0.0],
+            [100.0, 0.0],
+            [1.0; 4],
+            0.0,
+            Surface::Mirror,
+        )];
+        let bvh = MirrorBvh::build(&mirrors);
+        let trace = |ray: Ray| {
+            find_closest_mirror_reflections(&[ray], &mirrors, &bvh, 0.0, usize::MAX, false, 0)
+        };
+        // from outside the mirror the ray meets the edge and turns back, then leaves
+        let result = trace(Ray::new([-50.0, 0.0], [1.0, 0.0], [1.0; 4]));
+        assert_eq!(result.reflected_rays.len(), 1);
+        let reflected = result.reflected_rays[0];
+        assert_eq!(reflected.start_pos, [0.0, 0.0]);
+        assert_eq!(reflected.vector, [-1.0, 0.0]);
+        assert!(trace(reflected).reflected_rays.is_empty());
+        // from on the mirror it slides off the far end instead of bouncing between the ends
+        let on_it = Ray::new([30.0, 0.0], [1.0, 0.0], [1.0; 4]);
+        assert!(trace(on_it).reflected_rays.is_empty());
+        let back = Ray::new([30.0, 0.0], [-1.0, 0.0], [1.0; 4]);
+        assert!(trace(back).reflected_rays.is_empty());
+        // parallel but off the line passes by
+        let ray = Ray::new([-50.0, 1.0], [1.0, 0.0], [1.0; 4]);
+        assert!(!mirrors[0].intersect(ray).success);
+    }
+
+    #[test]
+    fn corners_reflect_off_the_averaged_normal() {
+        let mirrors = walls(
+            &[[0.0, 0.0], [100.0, 0.0], [100.0, 100.0], [0.0, 100.0]],
+            None,
+        );
+        let bvh = MirrorBvh::build(&mirrors);
+        // straight into the corner at the origin, off the diagonal
+        let ray = Ray::new([30.0, 60.0], [-1.0, -2.0], [1.0; 4]);
+        let result =
+            find_closest_mirror_reflections(&[ray], &mirrors, &bvh, 0.0, usize::MAX, false, 0);
+        let reflected = result.reflected_rays[0];
+        assert_eq!(reflected.start_pos, [0.0, 0.0]);
+        // mirrored on the corner's bisector, back into the box
+        assert!((reflected.vector[0] - 2.0).abs() < 1e-12);
+        assert!((reflected.vector[1] - 1.0).abs() < 1e-12);
+    }
+
+    fn trace_once(mirror: Mirror, ray: Ray) -> ReflectionHandlerResult {
+        let mirrors = vec![mirror];
+        find_closest_mirror_reflections(
+            &[ray],
+            &mirrors,
+            &MirrorBvh::build(&mirrors),
+            0.0,
+            usize::MAX,
+            false,
+            0,
+        )
+    }
+
+    #[test]
+    fn one_sided_mirrors_only_reflect_from_the_front() {
+        // runs to the right, so its left side is up on screen (y < 0)
+        let mirror = Mirror {
+            sides: Sides::One {
+                front: io::Side::Left,
+                back: io::BackFace::Absorb,
+            },
+            ..Mirror::segment([0.0, 0.0], [100.0, 0.0], [1.0; 4], 0.0, Surface::Mirror)
+        };
+        let from_above = Ray::new([50.0, -10.0], [0.0, 1.0], [1.0; 4]);
+        let from_below = Ray::new([50.0, 10.0], [0.0, -1.0], [1.0; 4]);
+
+        let front = trace_once(mirror, from_above);
+        assert_eq!(front.reflected_rays[0].vector, [0.0, -1.0]);
+        // the back still stops the ray, it just does not send anything on
+        let back = trace_once(mirror, from_below);
+        assert_eq!(back.draw_line.len(), 1);
+        assert!(back.reflected_rays.is_empty());
+
+        let passing = Mirror {
+            sides: Sides::One {
+                front: io::Side::Right,
+                back: io::BackFace::Pass,
+            },
+            ..mirror
+        };
+        assert_eq!(
+            trace_once(passing, from_below).reflected_rays[0].vector,
+            [0.0, 1.0]
+        );
+        let through = trace_once(passing, from_above).reflected_rays[0];
+        assert_eq!(
+            (through.start_pos, through.vector),
+            ([50.0, 0.0], [0.0, 1.0])
+        );
+        assert_eq!(mirror.back_ticks().len(), 10);
+        assert!(mirror.back_ticks().iter().all(|tick| tick[3] > 0.0));
+    }
+
+    #[test]
+    fn front_sides_follow_the_drawing_direction_on_curves() {
+        // runs clockwise on screen over the bottom of the circle, so left is outside
+        let arc = |front: io::Side| Mirror {
+            sides: Sides::One {
+                front,
+                back: io::BackFace::Absorb,
+            },
+            ..Mirror::arc(
+                [10.0, 0.0],
+                [0.0, 10.0],
+                [-10.0, 0.0],
+                [1.0; 4],
+                0.0,
+                Surface::Mirror,
+            )
+        };
+        let from_center = Ray::new([0.0, 0.0], [0.0, 1.0], [1.0; 4]);
+        let from_outside = Ray::new([0.0, 20.0], [0.0, -1.0], [1.0; 4]);
+        assert!(trace_once(arc(io::Side::Left), from_center)
+            .reflected_rays
+            .is_empty());
+        assert_eq!(
+            trace_once(arc(io::Side::Left), from_outside)
+                .reflected_rays
+                .len(),
+            1
+        );
+        assert_eq!(
+            trace_once(arc(io::Side::Right), from_center)
+                .reflected_rays
+                .len(),
+            1
+        );
+        // ticks on the inside for a mirror facing out
+        let tick = arc(io::Side::Left).back_ticks()[0];
+        assert!(tick[0].hypot(tick[1]) > tick[2].hypot(tick[3]));
+    }
+
+    // a wall along the x axis hit straight on from above by one ray
+    fn rough_hit(surface: Surface, seed: u64) -> Vec<Ray> {
+        let wall = Mirror::segment([-100.0, 0.0], [100.0, 0.0], [1.0; 4], 0.2, surface);
+        let ray = Ray::new([0.0, -10.0], [0.0, 1.0], [1.0; 4]);
+        let mut outgoing = Vec::new();
+        wall.interact(
+            &ray,
+            [0.0, 0.0],
+            wall.normal([0.0, 0.0]),
+            seed,
+            &mut outgoing,
+        );
+        outgoing
+    }
+
+    #[test]
+    fn diffuse_surfaces_scatter_by_lamberts_law() {
+        let samples = 20000;
+        let rays = rough_hit(Surface::Diffuse { samples }, 3);
+        assert_eq!(rays.len(), samples);
+        let energy: f64 = rays.iter().map(|ray| ray.energy).sum();
+        assert!((energy - 0.8).abs() < 1e-9);
+        // everything leaves on the side the ray came from, and the mean cosine to the
+        // normal of a 2d lambertian surface is pi / 4
+        assert!(rays.iter().all(|ray| ray.vector[1] <= 0.0));
+        let mean_cos = rays.iter().map(|ray| -ray.vector[1]).sum::<f64>() / samples as f64;
+        assert!(
+            (mean_cos - std::f64::consts::FRAC_PI_4).abs() < 0.01,
+            "{}",
+            mean_cos
+        );
+
+        // the seed decides the directions
+        let surface = Surface::Diffuse { samples: 4 };
+        assert_eq!(rough_hit(surface, 3), rough_hit(surface, 3));
+        assert_ne!(rough_hit(surface, 3), rough_hit(surface, 4));
+    }
+
+    #[test]
+    fn glossy_lobes_tighten_with_shininess() {
+        // a ray hitting at 45 degrees, so the mirror direction is [1, -1] / sqrt(2)
+        let spread = |shininess: f64| {
+            let wall = Mirror::segment(
+                [-100.0, 0.0],
+                [100.0, 0.0],
+                [1.0; 4],
+                0.0,
+                Surface::Glossy {
+                    shininess,
+                    samples: 5000,
+                },
+            );
+            let ray = Ray::new([-10.0, -10.0], [1.0, 1.0], [1.0; 4]);
+            let mut outgoing = Vec::new();
+            wall.interact(&ray, [0.0, 0.0], wall.normal([0.0, 0.0]), 9, &mut outgoing);
+            assert!(outgoing.iter().all(|ray| ray.vector[1] <= 0.0));
+            let mirrored = paths::unit([1.0, -1.0]);
+            outgoing
+                .iter()
+                .map(|ray| {
+                    let direction = paths::unit(ray.vector);
+                    direction[0] * mirrored[0] + direction[1] * mirrored[1]
+                })
+                .sum::<f64>()
+                / outgoing.len() as f64
+        };
+        let (rough, polished) = (spread(1.0), spread(200.0));
+        assert!(rough < polished, "{} {}", rough, polished);
+        assert!(polished > 0.99);
+
+        // the lobe is densest right at the mirror direction, not off to its sides
+        let wall = Mirror::segment(
+            [-100.0, 0.0],
+            [100.0, 0.0],
+            [1.0; 4],
+            0.0,
+            Surface::Glossy {
+                shininess: 20.0,
+                samples: 50000,
+            },
+        );
+        let ray = Ray::new([-10.0, -10.0], [1.0, 1.0], [1.0; 4]);
+        let mut outgoing = Vec::new();
+        wall.interact(&ray, [0.0, 0.0], wall.normal([0.0, 0.0]), 9, &mut outgoing);
+        // 5 degree bins, the middle one centered on the mirror direction
+        let mut bins = [0usize; 37];
+        for ray in &outgoing {
+            let angle = ray.vector[1].atan2(ray.vector[0]) + std::f64::consts::FRAC_PI_4;
+            let bin = (angle.to_degrees() / 5.0).round() as i64 + 18;
+            bins[bin as usize] += 1;
+        }
+        let densest = (0..bins.len()).max_by_key(|&bin| bins[bin]).unwrap();
+        assert_eq!(densest, 18, "{:?}", bins);
+    }
+
+    #[test]
+    fn splitters_send_part_of_the_light_on_and_reflect_the_rest() {
+        let splitter = |reflectance| {
+            Mirror::segment(
+                [-100.0, 0.0],
+                [100.0, 0.0],
+                [1.0; 4],
+                0.2,
+                Surface::Splitter { reflectance },
+            )
+        };
+        let ray = Ray::new([-10.0, -10.0], [1.0, 1.0], [1.0; 4]);
+        let rays = trace_once(splitter(0.25), ray).reflected_rays;
+        assert_eq!(rays.len(), 2);
+        assert_eq!(rays[0].vector, [1.0, -1.0]);
+        assert!((rays[0].energy - 0.2).abs() < 1e-12);
+        assert_eq!(rays[1].vector, [1.0, 1.0]);
+        assert!((rays[1].energy - 0.6).abs() < 1e-12);
+        assert!(rays
+            .iter()
+            .all(|child| child.id == ray.id && child.bounces == 1));
+
+        // all or nothing reflected is just a mirror or a window
+        assert_eq!(trace_once(splitter(1.0), ray).reflected_rays.len(), 1);
+        assert_eq!(
+            trace_once(splitter(0.0), ray).reflected_rays[0].vector,
+            [1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn pruning_keeps_the_order_of_the_survivors() {
+        let mut rays: Vec<Ray> = [0.5, 0.1, 0.9, 0.5, 0.3]
+            .into_iter()
+            .map(|energy| Ray {
+                energy,
+                ..Ray::new([0.0, 0.0], [1.0, 0.0], [1.0; 4])
+            })
+            .collect();
+        let dropped = prune_weakest(&mut rays, 3);
+        assert_eq!(dropped, [0.1, 0.3]);
+        let left: Vec<f64> = rays.iter().map(|ray| ray.energy).collect();
+        assert_eq!(left, [0.5, 0.9, 0.5]);
+    }
+
+    #[test]
+    fn generations_stay_capped_while_they_are_built() {
+        // every hit scatters 50 rays, far more than the cap lets through
+        let mirrors = vec![Mirror::segment(
+            [-1000.0, 0.0],
+            [1000.0, 0.0],
+            [1.0; 4],
+            0.0,
+            Surface::Diffuse { samples: 50 },
+        )];
+        let bvh = MirrorBvh::build(&mirrors);
+        let rays: Vec<Ray> = (0..600)
+            .map(|n| Ray {
+                energy: 1.0 - n as f64 / 1000.0,
+                id: n,
+                ..Ray::new([n as f64 - 300.0, -10.0], [0.3, 1.0], [1.0; 4])
+            })
+            .collect();
+        let capped = find_closest_mirror_reflections(&rays, &mirrors, &bvh, 0.0, 100, false, 0);
+        assert_eq!(capped.reflected_rays.len(), 100);
+        assert_eq!(capped.pruned_rays, 600 * 50 - 100);
+
+        // the same rays as pruning the whole generation at once, serial or parallel
+        let mut expected =
+            find_closest_mirror_reflections(&rays, &mirrors, &bvh, 0.0, usize::MAX, false, 0)
+                .reflected_rays;
+        prune_weakest(&mut expected, 100);
+        assert_eq!(capped.reflected_rays, expected);
+        let parallel =
+            find_closest_mirror_reflections_parallel(&rays, &mirrors, &bvh, 0.0, 100, false, 0);
+        assert_eq!(parallel, capped);
+    }
+}
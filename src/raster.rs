@@ -0,0 +1,403 @@
+use crate::physics::{ColorComponent, Segment};
+use crate::scene::Scene;
+use crate::spectrum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::str::FromStr;
+
+// color of the traced rays. very transparent, the picture comes from thousands of them
+// piling up on top of each other
+pub const RAY_COLOR: [ColorComponent; 4] = [1.0, 1.0, 0.2, 0.02];
+
+// width of the mirror lines in pixels
+pub const MIRROR_WIDTH: f64 = 3.0;
+
+// software image buffer to draw traces into without a gpu or a display.
+// pixels are stored row by row, top left first, as rgba floats in 0..1
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[ColorComponent; 4]>,
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize, background: [ColorComponent; 4]) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![background; width * height],
+        }
+    }
+
+    // blend a color over one pixel, the way the gpu blends with alpha (source over)
+    pub fn blend_pixel(&mut self, x: i64, y: i64, color: [ColorComponent; 4]) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+        let alpha = color[3];
+        for channel in 0..3 {
+            pixel[channel] = color[channel] * alpha + pixel[channel] * (1.0 - alpha);
+        }
+        pixel[3] = alpha + pixel[3] * (1.0 - alpha);
+    }
+
+    // draw a line [x1, y1, x2, y2] by stepping along it one pixel at a time.
+    // lines wider than a pixel get a square brush
+    pub fn draw_line(&mut self, color: [ColorComponent; 4], width: f64, coords: [f64; 4]) {
+        let [x1, y1, x2, y2] = coords;
+        if !coords.iter().all(|c| c.is_finite()) {
+            return;
+        }
+        let steps = (x2 - x1).abs().max((y2 - y1).abs()).ceil().max(1.0) as i64;
+        let brush = ((width - 1.0) / 2.0).round().max(0.0) as i64;
+        let (mut last_x, mut last_y) = (i64::MIN, i64::MIN);
+        for step in 0..=steps {
+            let t = step as f64 / steps as f64;
+            let x = (x1 + t * (x2 - x1)).floor() as i64;
+            let y = (y1 + t * (y2 - y1)).floor() as i64;
+            // rounding can land two steps on the same pixel, which would blend it twice
+            if x == last_x && y == last_y {
+                continue;
+            }
+            last_x = x;
+            last_y = y;
+            for dy in -brush..=brush {
+                for dx in -brush..=brush {
+                    self.blend_pixel(x + dx, y + dy, color);
+                }
+            }
+        }
+    }
+
+    // 8 bit rgba bytes, row by row. the canvas is treated as opaque
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            for channel in &pixel[0..3] {
+                bytes.push((channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+            bytes.push(255);
+        }
+        bytes
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), png::EncodingError> {
+        let file = File::create(path)?;
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgba8())
+    }
+}
+
+// color to draw a segment with: the ray color, or the color of its wavelength if it has
+// one, faded with the energy the ray had left
+pub fn segment_color(segment: &Segment, ray_color: [ColorComponent; 4]) -> [ColorComponent; 4] {
+    let mut color = ray_color;
+    if let Some(wavelength) = segment.wavelength {
+        let [r, g, b] = spectrum::wavelength_to_rgb(wavelength);
+        color = [r, g, b, ray_color[3]];
+    }
+    color[3] *= segment.energy as ColorComponent;
+    color
+}
+
+// draw traced segments with plain alpha blending, the way lines drawn on the gpu pile up
+pub fn draw_segments(canvas: &mut Canvas, segments: &[Segment]) {
+    for segment in segments {
+        canvas.draw_line(segment_color(segment, RAY_COLOR), 1.0, segment.coords);
+    }
+}
+
+// how the light collected in an Accumulator is turned into displayable brightness
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMap {
+    // proportional to the light, clipped at full brightness
+    Linear,
+    // logarithmic, the brightest pixel ends up at full brightness
+    Log,
+    // x / (1 + x), bright spots saturate smoothly instead of clipping
+    #[default]
+    Reinhard,
+}
+
+impl ToneMap {
+    pub const ALL: [ToneMap; 3] = [ToneMap::Linear, ToneMap::Log, ToneMap::Reinhard];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ToneMap::Linear => "linear",
+            ToneMap::Log => "log",
+            ToneMap::Reinhard => "reinhard",
+        }
+    }
+
+    // the next mapping, wrapping around. the window cycles through them with a key
+    pub fn next(&self) -> ToneMap {
+        let index = ToneMap::ALL.iter().position(|m| m == self).unwrap_or(0);
+        ToneMap::ALL[(index + 1) % ToneMap::ALL.len()]
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ToneMap, String> {
+        ToneMap::ALL
+            .into_iter()
+            .find(|mapping| mapping.as_str() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown tone map \"{}\" (expected \"linear\", \"log\" or \"reinhard\")",
+                    s
+                )
+            })
+    }
+}
+
+impl fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// brightness an average lit pixel gets at exposure 1, before the tone map
+const TONE_KEY: f64 = 0.5;
+
+// the gamma images are displayed with. applied last, so dim light stays visible
+const DISPLAY_GAMMA: f64 = 2.2;
+
+// light gathered per pixel, in linear rgb. unlike a Canvas nothing saturates here:
+// every segment adds its energy, so the picture shows how much light went where
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[f64; 3]>,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Accumulator {
+        Accumulator {
+            width,
+            height,
+            pixels: vec![[0.0; 3]; width * height],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.fill([0.0; 3]);
+    }
+
+    // add light to the four pixels around a point, weighted by how close their centers are
+    fn splat_point(&mut self, x: f64, y: f64, light: [f64; 3]) {
+        // pixel centers sit at .5
+        let (fx, fy) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (fx.floor(), fy.floor());
+        let (wx, wy) = (fx - x0, fy - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        for (dx, dy, weight) in [
+            (0, 0, (1.0 - wx) * (1.0 - wy)),
+            (1, 0, wx * (1.0 - wy)),
+            (0, 1, (1.0 - wx) * wy),
+            (1, 1, wx * wy),
+        ] {
+            let (px, py) = (x0 + dx, y0 + dy);
+            if px < 0 || py < 0 || px >= self.width as i64 || py >= self.height as i64 {
+                continue;
+            }
+            let pixel = &mut self.pixels[py as usize * self.width + px as usize];
+            for channel in 0..3 {
+                pixel[channel] += light[channel] * weight;
+            }
+        }
+    }
+
+    // spread `color` along a line, one sample per pixel of length. every pixel of length
+    // gets the same amount, so diagonal lines are as bright as straight ones
+    pub fn splat_line(&mut self, color: [f64; 3], coords: [f64; 4]) {
+        let [x1, y1, x2, y2] = coords;
+        if !coords.iter().all(|c| c.is_finite()) {
+            return;
+        }
+        let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+        let steps = length.ceil().max(1.0);
+        let per_step = length / steps;
+        let light = [
+            color[0] * per_step,
+            color[1] * per_step,
+            color[2] * per_step,
+        ];
+        for step in 0..steps as usize {
+            let t = (step as f64 + 0.5) / steps;
+            self.splat_point(x1 + t * (x2 - x1), y1 + t * (y2 - y1), light);
+        }
+    }
+
+    // add traced segments, each weighted with the energy its ray had left.
+    // the alpha of the color scales the whole segment
+    pub fn splat_segments(&mut self, segments: &[Segment], ray_color: [ColorComponent; 4]) {
+        for segment in segments {
+            let color = segment_color(segment, ray_color);
+            let weight = color[3] as f64;
+            let light = [
+                color[0] as f64 * weight,
+                color[1] as f64 * weight,
+                color[2] as f64 * weight,
+            ];
+            self.splat_line(light, segment.coords);
+        }
+    }
+
+    // turn the gathered light into a picture. brightness is relative to the average lit
+    // pixel, so the picture does not change with the number of rays or the image size.
+    // exposure scales it from there
+    pub fn tone_map(
+        &self,
+        mapping: ToneMap,
+        exposure: f64,
+        background: [ColorComponent; 4],
+    ) -> Canvas {
+        let luminance =
+            |pixel: &[f64; 3]| 0.2126 * pixel[0] + 0.7152 * pixel[1] + 0.0722 * pixel[2];
+        let (mut lit, mut total, mut brightest) = (0usize, 0.0, 0f64);
+        for pixel in &self.pixels {
+            let l = luminance(pixel);
+            if l > 0.0 {
+                lit += 1;
+                total += l;
+                brightest = brightest.max(l);
+            }
+        }
+        let mut canvas = Canvas::new(self.width, self.height, background);
+        if lit == 0 {
+            return canvas;
+        }
+        let scale = exposure * TONE_KEY * lit as f64 / total;
+        let brightest = brightest * scale;
+
+        for (out, pixel) in canvas.pixels.iter_mut().zip(&self.pixels) {
+            let l = luminance(pixel);
+            if l <= 0.0 {
+                continue;
+            }
+            let x = l * scale;
+            let mapped = match mapping {
+                ToneMap::Linear => x.min(1.0),
+                ToneMap::Log => (1.0 + x).ln() / (1.0 + brightest).ln(),
+                ToneMap::Reinhard => x / (1.0 + x),
+            };
+            // keep the hue: scale the color so its luminance becomes the mapped one
+            let ratio = mapped / l;
+            for channel in 0..3 {
+                let value = (pixel[channel] * ratio)
+                    .clamp(0.0, 1.0)
+                    .powf(1.0 / DISPLAY_GAMMA);
+                let value = value as ColorComponent;
+                // light adds to the background
+                out[channel] = (out[channel] + value).min(1.0);
+            }
+        }
+        canvas
+    }
+}
+
+pub fn draw_mirrors(canvas: &mut Canvas, scene: &Scene) {
+    for mirror in scene.mirrors() {
+        for coords in mirror.outline() {
+            canvas.draw_line(mirror.color, MIRROR_WIDTH, coords);
+        }
+        for coords in mirror.back_ticks() {
+            canvas.draw_line(mirror.color, 1.0, coords);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_blend_like_the_gpu() {
+        let mut canvas = Canvas::new(10, 10, [0.0, 0.0, 0.0, 1.0]);
+        let half_white = [1.0, 1.0, 1.0, 0.5];
+        canvas.draw_line(half_white, 1.0, [0.5, 2.5, 9.5, 2.5]);
+        canvas.draw_line(half_white, 1.0, [0.5, 2.5, 9.5, 2.5]);
+
+        // two half transparent passes: 0.5, then 0.5 + 0.5 * 0.5
+        for x in 0..10 {
+            assert_eq!(canvas.pixels[2 * 10 + x][0], 0.75);
+        }
+        // the rows next to the line stay untouched
+        assert_eq!(canvas.pixels[10][0], 0.0);
+        assert_eq!(canvas.pixels[3 * 10][0], 0.0);
+    }
+
+    #[test]
+    fn splatting_keeps_the_energy_of_every_segment() {
+        let total = |accumulator: &Accumulator| -> f64 {
+            accumulator.pixels.iter().map(|pixel| pixel[0]).sum()
+        };
+        let mut straight = Accumulator::new(50, 50);
+        straight.splat_line([1.0, 0.0, 0.0], [10.0, 10.0, 40.0, 10.0]);
+        let mut diagonal = Accumulator::new(50, 50);
+        diagonal.splat_line([1.0, 0.0, 0.0], [10.0, 10.0, 40.0, 40.0]);
+        // light is proportional to length, whatever the direction
+        assert!((total(&straight) - 30.0).abs() < 1e-9);
+        assert!((total(&diagonal) - 30.0 * 2f64.sqrt()).abs() < 1e-9);
+
+        // a ray with half its energy left adds half the light
+        let segment = |energy: f64| Segment {
+            coords: [5.0, 5.0, 25.0, 5.0],
+            energy,
+            wavelength: None,
+        };
+        let mut weighted = Accumulator::new(50, 50);
+        weighted.splat_segments(&[segment(0.5)], [1.0, 1.0, 1.0, 1.0]);
+        assert!((total(&weighted) - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tone_mapping_shows_density_not_ray_count() {
+        let segments = [
+            Segment {
+                coords: [0.0, 2.5, 20.0, 2.5],
+                energy: 1.0,
+                wavelength: None,
+            },
+            Segment {
+                coords: [0.0, 7.5, 20.0, 7.5],
+                energy: 0.25,
+                wavelength: None,
+            },
+        ];
+        let mut once = Accumulator::new(20, 10);
+        once.splat_segments(&segments, RAY_COLOR);
+        let mut ten_times = Accumulator::new(20, 10);
+        for _ in 0..10 {
+            ten_times.splat_segments(&segments, RAY_COLOR);
+        }
+        let black = [0.0, 0.0, 0.0, 1.0];
+        for mapping in ToneMap::ALL {
+            let picture = once.tone_map(mapping, 1.0, black);
+            // more rays along the same paths give the same picture
+            let again = ten_times.tone_map(mapping, 1.0, black);
+            for (a, b) in picture.pixels.iter().zip(&again.pixels) {
+                assert!((a[1] - b[1]).abs() < 1e-5, "{}", mapping);
+            }
+            // the stronger ray is brighter, untouched pixels stay black
+            let (bright, dim) = (picture.pixels[2 * 20 + 10], picture.pixels[7 * 20 + 10]);
+            assert!(bright[1] > dim[1] && dim[1] > 0.0, "{}", mapping);
+            assert_eq!(picture.pixels[5 * 20 + 10], black);
+            assert_eq!(mapping.as_str().parse(), Ok(mapping));
+        }
+        assert!("gamma".parse::<ToneMap>().is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::bvh::MirrorBvh;
+use crate::io;
+use crate::light::{self, LightSource};
+use crate::physics::{self, Hit, Mirror, Ray};
+
+// a set of mirrors ready to be traced. keeps the acceleration structure in sync with
+// the mirrors, so change them through set_mirrors
+#[derive(Debug, Clone)]
+pub struct Scene {
+    mirrors: Vec<Mirror>,
+    bvh: MirrorBvh,
+    pub lights: Vec<LightSource>,
+}
+
+impl Scene {
+    pub fn new(mirrors: Vec<Mirror>) -> Scene {
+        let bvh = MirrorBvh::build(&mirrors);
+        Scene {
+            mirrors,
+            bvh,
+            lights: Vec::new(),
+        }
+    }
+
+    // load a scene file and resolve its coordinates against the given window size
+    pub fn load(path: &str, window_dimensions: [f64; 2]) -> Result<Scene, io::SceneError> {
+        Scene::from_json(&io::read_json(path)?, window_dimensions)
+    }
+
+    // resolve an already loaded scene file. call this again when the window size changes
+    pub fn from_json(
+        json_data: &io::JSON,
+        window_dimensions: [f64; 2],
+    ) -> Result<Scene, io::SceneError> {
+        let mut scene = Scene::new(physics::mirrors_from_json(json_data, window_dimensions)?);
+        scene.lights = light::lights_from_json(json_data, window_dimensions)?;
+        Ok(scene)
+    }
+
+    pub fn mirrors(&self) -> &[Mirror] {
+        &self.mirrors
+    }
+
+    pub fn set_mirrors(&mut self, mirrors: Vec<Mirror>) {
+        self.bvh = MirrorBvh::build(&mirrors);
+        self.mirrors = mirrors;
+    }
+
+    pub fn bvh(&self) -> &MirrorBvh {
+        &self.bvh
+    }
+
+    // the rays a trace of this scene starts with. a scene without lights of its own gets
+    // a point light at `fallback_position`
+    pub fn emit(&self, default_ray_count: usize, fallback_position: [f64; 2]) -> Vec<Ray> {
+        if self.lights.is_empty() {
+            return LightSource::point(fallback_position, default_ray_count).rays(0);
+        }
+        light::emit(&self.lights, default_ray_count)
+    }
+
+    // the closest mirror the ray runs into, if any
+    pub fn closest_hit(&self, ray: &Ray) -> Option<Hit> {
+        self.bvh.closest_hit(ray, &self.mirrors)
+    }
+}
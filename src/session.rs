@@ -0,0 +1,170 @@
+use crate::history::History;
+use crate::io;
+use crate::physics::ColorComponent;
+use crate::raster::ToneMap;
+use crate::tracer;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// version of the session format, same idea as io::SCENE_VERSION
+pub const SESSION_VERSION: u32 = 1;
+
+// everything needed to reopen the window on exactly the same picture: the scene itself
+// (so later edits to the scene file do not change the session) and every setting
+// the trace and the drawing depend on
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub version: u32,
+    // where the scene came from. saving the scene from a reopened session writes there
+    pub scene_path: String,
+    pub scene: io::JSON,
+    // position of the light source in window pixels
+    pub source_pos: [f64; 2],
+    pub ray_count: usize,
+    pub max_reflections: usize,
+    pub energy_threshold: f64,
+    // sessions from before rough surfaces get seed 0, which is what they were traced with
+    #[serde(default)]
+    pub seed: u64,
+    // sessions from before the ray cap get the default one
+    #[serde(default = "default_max_rays")]
+    pub max_rays: usize,
+    pub ray_color: [ColorComponent; 4],
+    pub view: ViewSettings,
+    // the edits that can still be undone
+    #[serde(default)]
+    pub history: History,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct ViewSettings {
+    // window size in pixels. the scene and the source position are resolved against it
+    pub window_size: [f64; 2],
+    pub editing: bool,
+    // sessions from before tone mapping get the defaults
+    #[serde(default)]
+    pub tone_map: ToneMap,
+    #[serde(default = "default_exposure")]
+    pub exposure: f64,
+}
+
+fn default_exposure() -> f64 {
+    1.0
+}
+
+fn default_max_rays() -> usize {
+    tracer::DEFAULT_MAX_RAYS
+}
+
+pub fn read_session(path: &str) -> Result<Session, io::SceneError> {
+    let data = fs::read_to_string(path)?;
+    let version = io::file_version(&data)?;
+    let mut value: serde_json::Value = serde_json::from_str(&data)?;
+    if version > SESSION_VERSION as u64 {
+        return Err(io::SceneError::UnsupportedVersion {
+            found: version,
+            supported: SESSION_VERSION,
+        });
+    }
+    // the scene inside goes through the same migration as a scene file on its own
+    let Some(session) = value.as_object_mut() else {
+        return Err(not_a_session("a session has to be a json object"));
+    };
+    let Some(scene) = session.remove("scene") else {
+        return Err(not_a_session("missing field `scene`"));
+    };
+    let scene = io::parse_json(&scene.to_string())?;
+    session.insert("scene".to_string(), serde_json::to_value(&scene)?);
+    Ok(serde_json::from_value::<Session>(value)?)
+}
+
+// a session file that is json, but not shaped like a session. the problem is with the
+// file as a whole, so it points at its start
+fn not_a_session(message: &str) -> io::SceneError {
+    io::SceneError::Parse {
+        line: 1,
+        column: 1,
+        message: message.to_string(),
+    }
+}
+
+pub fn write_session(path: &str, session: &Session) -> std::io::Result<()> {
+    let mut session = session.clone();
+    session.version = SESSION_VERSION;
+    session.scene.version = io::SCENE_VERSION;
+    let data = serde_json::to_string_pretty(&session)?;
+    fs::write(path, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Edit;
+
+    #[test]
+    fn sessions_survive_a_round_trip() {
+        let mut scene = io::read_json("assets/optical_bench.json").unwrap();
+        let mut history = History::new(10);
+        let mirror = io::Mirror::segment([0.1, 0.1], [0.2, 0.2], 0.0);
+        let edit = Edit::Insert {
+            index: scene.mirrors.len(),
+            mirror,
+        };
+        edit.apply(&mut scene);
+        history.push(edit);
+
+        let session = Session {
+            version: SESSION_VERSION,
+            scene_path: "assets/optical_bench.json".to_string(),
+            scene,
+            source_pos: [120.5, 300.0],
+            ray_count: 1234,
+            max_reflections: 7,
+            energy_threshold: 0.05,
+            seed: 42,
+            max_rays: 5000,
+            ray_color: [0.2, 0.4, 1.0, 0.1],
+            view: ViewSettings {
+                window_size: [640.0, 480.0],
+                editing: true,
+                tone_map: ToneMap::Log,
+                exposure: 2.0,
+            },
+            history,
+        };
+        let path = std::env::temp_dir().join(format!("session_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_session(path, &session).unwrap();
+        let mut loaded = read_session(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.source_pos, session.source_pos);
+        assert_eq!(loaded.ray_count, 1234);
+        assert_eq!(loaded.max_reflections, 7);
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(loaded.max_rays, 5000);
+        assert_eq!(loaded.ray_color, session.ray_color);
+        assert_eq!(loaded.view, session.view);
+        assert_eq!(loaded.scene.mirrors, session.scene.mirrors);
+        assert_eq!(loaded.history, session.history);
+
+        // the history still applies to the scene it came with
+        assert!(loaded.history.undo(&mut loaded.scene).is_some());
+        assert_eq!(loaded.scene.mirrors.len(), session.scene.mirrors.len() - 1);
+    }
+
+    #[test]
+    fn json_that_is_not_a_session_is_a_parse_error() {
+        let path = std::env::temp_dir().join(format!("not_a_session_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        for data in ["[]", "5", r#"{"version": 1}"#, r#"{"version": -1}"#] {
+            fs::write(path, data).unwrap();
+            assert!(
+                matches!(read_session(path), Err(io::SceneError::Parse { .. })),
+                "{}",
+                data
+            );
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
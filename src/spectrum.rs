@@ -0,0 +1,195 @@
+use crate::physics::ColorComponent;
+use serde::{Deserialize, Serialize};
+
+// the visible range in nanometers, the wavelengths white light is made of
+pub const VISIBLE_MIN: f64 = 380.0;
+pub const VISIBLE_MAX: f64 = 750.0;
+
+// wavelength used for rays without one, the sodium d line refractive indices are quoted at
+pub const REFERENCE_WAVELENGTH: f64 = 589.3;
+
+// the wavelengths a light sends out
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Spectrum {
+    // the whole visible range, evenly
+    White,
+    // one wavelength, e.g. a laser
+    Monochromatic(f64),
+    // everything between two wavelengths, evenly
+    Band(f64, f64),
+}
+
+impl Spectrum {
+    // wavelength of the n-th of `count` rays. consecutive rays get wavelengths far apart
+    // (steps of sqrt(2) - 1 through the band), so a point source does not paint a rainbow
+    // around itself. the steps differ from the golden angle the line emitter turns its
+    // directions by, so wavelength and direction do not line up there either
+    pub fn sample(&self, n: usize, count: usize) -> f64 {
+        let (min, max) = match *self {
+            Spectrum::White => (VISIBLE_MIN, VISIBLE_MAX),
+            Spectrum::Monochromatic(wavelength) => return wavelength,
+            Spectrum::Band(min, max) => (min, max),
+        };
+        let step = 2f64.sqrt() - 1.0;
+        // the offset keeps a single ray in the middle of the band
+        let t = if count <= 1 {
+            0.5
+        } else {
+            (0.5 + n as f64 * step).fract()
+        };
+        min + t * (max - min)
+    }
+}
+
+// refractive index of a material, either fixed or depending on the wavelength.
+// in scene files a plain number is a constant index
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    Dispersive(Dispersion),
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Dispersion {
+    // n = a + b / l^2 + c / l^4 with l in micrometers. good enough for most glasses
+    Cauchy {
+        a: f64,
+        b: f64,
+        #[serde(default)]
+        c: f64,
+    },
+    // n^2 = 1 + sum of b_i l^2 / (l^2 - c_i) with l in micrometers, the form glass
+    // catalogues give their coefficients in
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl RefractiveIndex {
+    // the index at a wavelength in nanometers
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let l = wavelength / 1000.0;
+        let l_sq = l * l;
+        match *self {
+            RefractiveIndex::Constant(n) => n,
+            RefractiveIndex::Dispersive(Dispersion::Cauchy { a, b, c }) => {
+                a + b / l_sq + c / (l_sq * l_sq)
+            }
+            RefractiveIndex::Dispersive(Dispersion::Sellmeier { b, c }) => {
+                let sum: f64 = (0..3).map(|i| b[i] * l_sq / (l_sq - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+impl Default for RefractiveIndex {
+    fn default() -> RefractiveIndex {
+        RefractiveIndex::Constant(1.0)
+    }
+}
+
+// display color of a wavelength in nanometers, after Dan Bruton's approximation.
+// fades out towards the ends of the visible range and is black outside of it
+pub fn wavelength_to_rgb(wavelength: f64) -> [ColorComponent; 3] {
+    let w = wavelength;
+    let (r, g, b) = if (380.0..440.0).contains(&w) {
+        (-(w - 440.0) / (440.0 - 380.0), 0.0, 1.0)
+    } else if (440.0..490.0).contains(&w) {
+        (0.0, (w - 440.0) / (490.0 - 440.0), 1.0)
+    } else if (490.0..510.0).contains(&w) {
+        (0.0, 1.0, -(w - 510.0) / (510.0 - 490.0))
+    } else if (510.0..580.0).contains(&w) {
+        ((w - 510.0) / (580.0 - 510.0), 1.0, 0.0)
+    } else if (580.0..645.0).contains(&w) {
+        (1.0, -(w - 645.0) / (645.0 - 580.0), 0.0)
+    } else if (645.0..=750.0).contains(&w) {
+        (1.0, 0.0, 0.0)
+    } else {
+        (0.0, 0.0, 0.0)
+    };
+    // the eye is less sensitive at the edges
+    let intensity = if (380.0..420.0).contains(&w) {
+        0.3 + 0.7 * (w - 380.0) / (420.0 - 380.0)
+    } else if (700.0..=750.0).contains(&w) {
+        0.3 + 0.7 * (750.0 - w) / (750.0 - 700.0)
+    } else {
+        1.0
+    };
+    [
+        (r * intensity) as ColorComponent,
+        (g * intensity) as ColorComponent,
+        (b * intensity) as ColorComponent,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glass_bends_blue_more_than_red() {
+        // schott n-bk7, catalogue value n_d = 1.5168
+        let bk7 = RefractiveIndex::Dispersive(Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        });
+        assert!((bk7.at(REFERENCE_WAVELENGTH) - 1.5168).abs() < 1e-4);
+        assert!(bk7.at(450.0) > bk7.at(650.0));
+
+        let cauchy = RefractiveIndex::Dispersive(Dispersion::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+            c: 0.0,
+        });
+        assert!((cauchy.at(500.0) - (1.5046 + 0.0042 / 0.25)).abs() < 1e-12);
+        assert_eq!(RefractiveIndex::Constant(1.33).at(400.0), 1.33);
+    }
+
+    #[test]
+    fn indices_read_as_numbers_or_formulas() {
+        let parsed: Vec<RefractiveIndex> = serde_json::from_str(
+            r#"[1.5, {"cauchy": {"a": 1.5, "b": 0.004}},
+                {"sellmeier": {"b": [1, 0, 0], "c": [0, 0, 0]}}]"#,
+        )
+        .unwrap();
+        assert_eq!(parsed[0], RefractiveIndex::Constant(1.5));
+        assert_eq!(
+            parsed[1],
+            RefractiveIndex::Dispersive(Dispersion::Cauchy {
+                a: 1.5,
+                b: 0.004,
+                c: 0.0
+            })
+        );
+        // n^2 = 1 + 1 gives sqrt(2) everywhere
+        assert!((parsed[2].at(500.0) - 2f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn spectra_cover_their_range() {
+        let count = 1000;
+        let samples: Vec<f64> = (0..count)
+            .map(|n| Spectrum::White.sample(n, count))
+            .collect();
+        assert!(samples
+            .iter()
+            .all(|w| (VISIBLE_MIN..VISIBLE_MAX).contains(w)));
+        // every tenth of the range gets about a tenth of the rays
+        for bucket in 0..10 {
+            let low = VISIBLE_MIN + bucket as f64 * 37.0;
+            let hits = samples
+                .iter()
+                .filter(|w| (low..low + 37.0).contains(*w))
+                .count();
+            assert!((90..=110).contains(&hits), "{} rays at {}", hits, low);
+        }
+        assert_eq!(Spectrum::Monochromatic(532.0).sample(7, 10), 532.0);
+        assert_eq!(wavelength_to_rgb(700.0), [1.0, 0.0, 0.0]);
+        assert_eq!(wavelength_to_rgb(900.0), [0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::detector::DetectorHit;
+use crate::paths::PathEvent;
+use crate::physics::{self, Ray, ReflectionHandlerResult, Segment};
+use crate::scene::Scene;
+
+// settings for tracing rays through a scene
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tracer {
+    // bounce generations a full trace runs for
+    pub max_bounces: usize,
+    // rays carrying less than this fraction of their emitted energy stop bouncing
+    pub energy_threshold: f64,
+    // spread the rays of each generation over the thread pool. gives the same result to the
+    // bit either way
+    pub parallel: bool,
+    // keep a PathEvent for every hit. off by default, big traces have millions of them
+    pub record_paths: bool,
+    // where diffuse and glossy surfaces scatter to. the same seed gives the same trace
+    pub seed: u64,
+    // most rays a generation may pass on. splitters and rough surfaces with several samples
+    // multiply the rays every bounce, past this the weakest ones get dropped. a generation
+    // never holds more than twice this many rays while it is being traced
+    pub max_rays: usize,
+}
+
+pub const DEFAULT_MAX_RAYS: usize = 1_000_000;
+
+impl Default for Tracer {
+    fn default() -> Tracer {
+        Tracer {
+            max_bounces: 50,
+            energy_threshold: 0.01,
+            parallel: true,
+            record_paths: false,
+            seed: 0,
+            max_rays: DEFAULT_MAX_RAYS,
+        }
+    }
+}
+
+// everything a full trace produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    // every piece of every ray path, generation after generation
+    pub segments: Vec<Segment>,
+    // the rays still alive when the bounce limit was hit
+    pub remaining_rays: Vec<Ray>,
+    // every ray that ended on a detector, generation after generation
+    pub detector_hits: Vec<DetectorHit>,
+    // every hit, generation after generation, if the tracer records paths
+    pub path_events: Vec<PathEvent>,
+    // number of generations actually traced
+    pub bounces: usize,
+    // rays dropped to stay under max_rays, and the energy they carried away
+    pub pruned_rays: usize,
+    pub pruned_energy: f64,
+}
+
+impl Tracer {
+    pub fn new() -> Tracer {
+        Tracer::default()
+    }
+
+    // trace one generation: every ray to its next hit, and the rays leaving those hits
+    pub fn step(&self, scene: &Scene, rays: &[Ray]) -> ReflectionHandlerResult {
+        if self.parallel {
+            physics::find_closest_mirror_reflections_parallel(
+                rays,
+                scene.mirrors(),
+                scene.bvh(),
+                self.energy_threshold,
+                self.max_rays,
+                self.record_paths,
+                self.seed,
+            )
+        } else {
+            physics::find_closest_mirror_reflections(
+                rays,
+                scene.mirrors(),
+                scene.bvh(),
+                self.energy_threshold,
+                self.max_rays,
+                self.record_paths,
+                self.seed,
+            )
+        }
+    }
+
+    // run generations until every ray has escaped or died, or max_bounces is reached
+    pub fn trace(&self, scene: &Scene, rays: Vec<Ray>) -> Trace {
+        let mut trace = Trace {
+            segments: Vec::new(),
+            remaining_rays: rays,
+            detector_hits: Vec::new(),
+            path_events: Vec::new(),
+            bounces: 0,
+            pruned_rays: 0,
+            pruned_energy: 0.0,
+        };
+        while trace.bounces < self.max_bounces && !trace.remaining_rays.is_empty() {
+            let result = self.step(scene, &trace.remaining_rays);
+            trace.segments.extend(result.draw_line);
+            trace.remaining_rays = result.reflected_rays;
+            trace.detector_hits.extend(result.detector_hits);
+            trace.path_events.extend(result.path_events);
+            trace.pruned_rays += result.pruned_rays;
+            trace.pruned_energy += result.pruned_energy;
+            trace.bounces += 1;
+        }
+        trace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::{Mirror, Surface};
+
+    #[test]
+    fn crowded_generations_drop_their_weakest_rays() {
+        // a splitter reflecting 30%, hit by ten rays that each turn into two
+        let scene = Scene::new(vec![Mirror::segment(
+            [100.0, -100.0],
+            [100.0, 100.0],
+            [1.0; 4],
+            0.0,
+            Surface::Splitter { reflectance: 0.3 },
+        )]);
+        let rays: Vec<Ray> = (0..10)
+            .map(|n| Ray::new([0.0, n as f64 * 5.0], [1.0, 0.0], [1.0; 4]))
+            .collect();
+        let tracer = Tracer {
+            max_rays: 10,
+            max_bounces: 1,
+            ..Tracer::default()
+        };
+        let result = tracer.step(&scene, &rays);
+        assert_eq!(result.reflected_rays.len(), 10);
+        assert!(result
+            .reflected_rays
+            .iter()
+            .all(|ray| ray.vector == [1.0, 0.0]));
+        assert_eq!(result.pruned_rays, 10);
+        assert!((result.pruned_energy - 3.0).abs() < 1e-9);
+
+        let trace = tracer.trace(&scene, rays.clone());
+        assert_eq!(trace.remaining_rays, result.reflected_rays);
+        assert_eq!(trace.pruned_rays, 10);
+        // without a cap both halves go on
+        let uncapped = Tracer {
+            max_rays: DEFAULT_MAX_RAYS,
+            ..tracer
+        };
+        assert_eq!(uncapped.trace(&scene, rays).remaining_rays.len(), 20);
+    }
+}
@@ -0,0 +1,293 @@
+use crate::detector::DetectorHit;
+use crate::physics::{Ray, Segment};
+use crate::scene::Scene;
+use crate::tracer::Tracer;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// generations the worker can get ahead of whoever draws them before it waits
+const BATCH_QUEUE: usize = 4;
+
+// what one traced generation produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    // which restart this belongs to, batches of older runs never reach the caller
+    pub run: u64,
+    // 0 for the generation leaving the lights
+    pub generation: usize,
+    pub segments: Vec<Segment>,
+    pub detector_hits: Vec<DetectorHit>,
+    // rays left to trace after this generation
+    pub live_rays: usize,
+    // rays this generation dropped to stay under the tracer's max_rays
+    pub pruned_rays: usize,
+    // the last batch of the run: every ray is gone or the bounce limit is reached
+    pub finished: bool,
+}
+
+enum Command {
+    Restart {
+        run: u64,
+        scene: Scene,
+        rays: Vec<Ray>,
+        tracer: Tracer,
+    },
+    Pause,
+    Resume,
+    Step,
+    Stop,
+}
+
+// a trace running on its own thread, one generation at a time. the thread sends every
+// generation as a Batch, so drawing never waits for tracing and the other way round
+pub struct TraceWorker {
+    commands: Sender<Command>,
+    batches: Receiver<Batch>,
+    handle: Option<JoinHandle<()>>,
+    run: u64,
+    paused: bool,
+}
+
+impl TraceWorker {
+    pub fn spawn() -> TraceWorker {
+        let (commands, command_receiver) = mpsc::channel();
+        let (batch_sender, batches) = mpsc::sync_channel(BATCH_QUEUE);
+        let handle = thread::spawn(move || work(command_receiver, batch_sender));
+        TraceWorker {
+            commands,
+            batches,
+            handle: Some(handle),
+            run: 0,
+            paused: false,
+        }
+    }
+
+    // drop whatever is being traced and start over with these rays
+    pub fn restart(&mut self, scene: Scene, rays: Vec<Ray>, tracer: Tracer) {
+        self.run += 1;
+        self.send(Command::Restart {
+            run: self.run,
+            scene,
+            rays,
+            tracer,
+        });
+    }
+
+    // stop after the generation being traced, restarts stay paused too
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.send(Command::Pause);
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.send(Command::Resume);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // trace one more generation while paused
+    pub fn step(&self) {
+        self.send(Command::Step);
+    }
+
+    // every batch of the current run that is ready, without waiting
+    pub fn try_batches(&self) -> Vec<Batch> {
+        let mut ready = Vec::new();
+        loop {
+            match self.batches.try_recv() {
+                Ok(batch) if batch.run == self.run => ready.push(batch),
+                Ok(_) => {}
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return ready,
+            }
+        }
+    }
+
+    // wait up to `timeout` for the next batch of the current run
+    pub fn next_batch(&self, timeout: Duration) -> Option<Batch> {
+        loop {
+            match self.batches.recv_timeout(timeout) {
+                Ok(batch) if batch.run == self.run => return Some(batch),
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return None
+                }
+            }
+        }
+    }
+
+    fn send(&self, command: Command) {
+        // the thread only goes away in drop, a failed send has nowhere to go
+        let _ = self.commands.send(command);
+    }
+}
+
+impl Drop for TraceWorker {
+    fn drop(&mut self) {
+        self.send(Command::Stop);
+        // the thread may be waiting for room in the queue, keep emptying it until it is gone
+        while self.batches.recv().is_ok() {}
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// the run being traced on the worker thread
+struct Run {
+    run: u64,
+    scene: Scene,
+    rays: Vec<Ray>,
+    tracer: Tracer,
+    generation: usize,
+}
+
+fn work(commands: Receiver<Command>, batches: SyncSender<Batch>) {
+    let mut current: Option<Run> = None;
+    let mut paused = false;
+    // generations still to trace while paused
+    let mut steps = 0usize;
+
+    loop {
+        // with nothing to do, sleep until told otherwise. otherwise only look at what
+        // arrived in the meantime
+        let busy = current.is_some() && (!paused || steps > 0);
+        let command = if busy {
+            match commands.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        } else {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return,
+            }
+        };
+        match command {
+            Some(Command::Restart {
+                run,
+                scene,
+                rays,
+                tracer,
+            }) => {
+                current = Some(Run {
+                    run,
+                    scene,
+                    rays,
+                    tracer,
+                    generation: 0,
+                });
+                steps = 0;
+                continue;
+            }
+            Some(Command::Pause) => {
+                paused = true;
+                continue;
+            }
+            Some(Command::Resume) => {
+                paused = false;
+                steps = 0;
+                continue;
+            }
+            Some(Command::Step) => {
+                steps += 1;
+                continue;
+            }
+            Some(Command::Stop) => return,
+            None => {}
+        }
+
+        let Some(run) = current.as_mut() else {
+            continue;
+        };
+        let result = run.tracer.step(&run.scene, &run.rays);
+        run.rays = result.reflected_rays;
+        run.generation += 1;
+        let finished = run.rays.is_empty() || run.generation >= run.tracer.max_bounces;
+        let batch = Batch {
+            run: run.run,
+            generation: run.generation - 1,
+            segments: result.draw_line,
+            detector_hits: result.detector_hits,
+            live_rays: run.rays.len(),
+            pruned_rays: result.pruned_rays,
+            finished,
+        };
+        steps = steps.saturating_sub(1);
+        if finished {
+            current = None;
+        }
+        if batches.send(batch).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics;
+
+    fn bundled_scene() -> Scene {
+        Scene::load("assets/mirrors.json", [800.0, 800.0]).unwrap()
+    }
+
+    #[test]
+    fn batches_add_up_to_a_full_trace() {
+        let scene = bundled_scene();
+        let rays = physics::generate_rays(50.0, [900.0, 500.0]);
+        let tracer = Tracer {
+            max_bounces: 8,
+            ..Tracer::default()
+        };
+        let expected = tracer.trace(&scene, rays.clone());
+        // still bouncing when the limit stops them, so there are batches to add up
+        assert_eq!(expected.bounces, 8);
+
+        let mut worker = TraceWorker::spawn();
+        worker.restart(scene, rays, tracer);
+        let mut segments = Vec::new();
+        let mut generations = 0;
+        while let Some(batch) = worker.next_batch(Duration::from_secs(10)) {
+            assert_eq!(batch.generation, generations);
+            generations += 1;
+            segments.extend(batch.segments);
+            if batch.finished {
+                break;
+            }
+        }
+        assert_eq!(generations, expected.bounces);
+        assert_eq!(segments, expected.segments);
+    }
+
+    #[test]
+    fn a_paused_worker_only_steps_when_told() {
+        let mut worker = TraceWorker::spawn();
+        worker.pause();
+        worker.restart(
+            bundled_scene(),
+            physics::generate_rays(10.0, [900.0, 500.0]),
+            Tracer::default(),
+        );
+        assert!(worker.next_batch(Duration::from_millis(100)).is_none());
+
+        worker.step();
+        let batch = worker.next_batch(Duration::from_secs(10)).unwrap();
+        assert_eq!(batch.generation, 0);
+        assert!(worker.next_batch(Duration::from_millis(100)).is_none());
+
+        // a restart drops what is left of the old run
+        worker.resume();
+        worker.restart(
+            bundled_scene(),
+            physics::generate_rays(10.0, [900.0, 500.0]),
+            Tracer::default(),
+        );
+        let batch = worker.next_batch(Duration::from_secs(10)).unwrap();
+        assert_eq!((batch.run, batch.generation), (2, 0));
+    }
+}
@@ -0,0 +1,219 @@
+use piston2d_testing::{detector, physics, Mirror, Ray, Scene, Surface, Tracer};
+
+// closed square box of perfect mirrors, 100 wide
+fn mirror_box() -> Scene {
+    let corners = [[0.0, 0.0], [100.0, 0.0], [100.0, 100.0], [0.0, 100.0]];
+    Scene::new(
+        (0..4)
+            .map(|n| {
+                Mirror::segment(
+                    corners[n],
+                    corners[(n + 1) % 4],
+                    [1.0; 4],
+                    0.0,
+                    Surface::Mirror,
+                )
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn full_trace_runs_without_a_window() {
+    let scene = mirror_box();
+    let tracer = Tracer {
+        max_bounces: 20,
+        ..Tracer::default()
+    };
+    let trace = tracer.trace(&scene, physics::generate_rays(100.0, [30.0, 60.0]));
+
+    // nothing escapes a closed box of perfect mirrors
+    assert_eq!(trace.bounces, 20);
+    assert_eq!(trace.segments.len(), 100 * 20);
+    assert_eq!(trace.remaining_rays.len(), 100);
+}
+
+#[test]
+fn trace_stops_once_every_ray_is_gone() {
+    // a single absorbing wall eats the ray on the first bounce
+    let scene = Scene::new(vec![Mirror::segment(
+        [50.0, -10.0],
+        [50.0, 10.0],
+        [1.0; 4],
+        1.0,
+        Surface::Mirror,
+    )]);
+    let trace = Tracer::new().trace(&scene, vec![Ray::new([0.0, 0.0], [1.0, 0.0], [1.0; 4])]);
+
+    assert_eq!(trace.bounces, 1);
+    assert_eq!(trace.segments.len(), 1);
+    assert_eq!(trace.segments[0].coords, [0.0, 0.0, 50.0, 0.0]);
+    assert!(trace.remaining_rays.is_empty());
+}
+
+#[test]
+fn bundled_scene_loads_headless() {
+    let scene = Scene::load("assets/mirrors.json", [800.0, 800.0]).unwrap();
+    assert!(!scene.mirrors().is_empty());
+    let serial = Tracer {
+        parallel: false,
+        max_bounces: 5,
+        ..Tracer::default()
+    };
+    let parallel = Tracer {
+        parallel: true,
+        ..serial
+    };
+    let rays = physics::generate_rays(500.0, [400.0, 300.0]);
+    assert_eq!(
+        serial.trace(&scene, rays.clone()),
+        parallel.trace(&scene, rays)
+    );
+}
+
+#[test]
+fn scene_lights_are_traced_together() {
+    let scene = Scene::load("assets/light_sources.json", [800.0, 800.0]).unwrap();
+    assert_eq!(scene.lights.len(), 5);
+    // 2000 + 200 + 500 + 1000 + the laser, the ray count only matters for scenes without lights
+    let rays = scene.emit(10, [400.0, 400.0]);
+    assert_eq!(rays.len(), 3701);
+
+    let trace = Tracer::new().trace(&scene, rays);
+    // the first bounce draws one segment per ray, and everything ends in the absorbing box
+    assert!(trace.segments.len() >= 3701);
+    assert!(trace.remaining_rays.is_empty());
+
+    // without lights the scene falls back to a point at the given position
+    let unlit = Scene::load("assets/mirrors.json", [800.0, 800.0]).unwrap();
+    let rays = unlit.emit(10, [400.0, 300.0]);
+    assert_eq!(rays, physics::generate_rays(10.0, [400.0, 300.0]));
+}
+
+#[test]
+fn detector_measures_the_spectrum_behind_a_prism() {
+    let scene = Scene::load("assets/prism.json", [800.0, 800.0]).unwrap();
+    let trace = Tracer::new().trace(&scene, scene.emit(10, [400.0, 400.0]));
+    let hits = &trace.detector_hits;
+    // every ray of the beam goes in and out of the prism, then ends on the screen
+    assert_eq!(hits.len(), 3000);
+    assert!(hits.iter().all(|hit| hit.bounces == 2));
+
+    // violet is bent further than red, so it lands further along the screen
+    let mean_position = |range: std::ops::Range<f64>| {
+        let positions: Vec<f64> = hits
+            .iter()
+            .filter(|hit| range.contains(&hit.wavelength.unwrap()))
+            .map(|hit| hit.position)
+            .collect();
+        positions.iter().sum::<f64>() / positions.len() as f64
+    };
+    assert!(mean_position(380.0..450.0) > mean_position(650.0..750.0));
+
+    let profiles = detector::profiles(scene.mirrors(), hits);
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[0].bins.len(), 60);
+    let binned: f64 = profiles[0].bins.iter().map(|bin| bin.energy).sum();
+    let total: f64 = hits.iter().map(|hit| hit.energy).sum();
+    assert!((binned - total).abs() < 1e-9);
+}
+
+#[test]
+fn single_rays_can_be_followed_through_the_scene() {
+    let scene = Scene::load("assets/prism.json", [800.0, 800.0]).unwrap();
+    let rays = scene.emit(10, [400.0, 400.0]);
+    let recording = Tracer {
+        record_paths: true,
+        ..Tracer::default()
+    };
+    let trace = recording.trace(&scene, rays.clone());
+    // recording changes nothing about the trace itself
+    let plain = Tracer::default().trace(&scene, rays);
+    assert_eq!(trace.segments, plain.segments);
+    assert!(plain.path_events.is_empty());
+
+    // the middle ray of the beam: into the prism, out of it, onto the detector
+    let path: Vec<_> = trace
+        .path_events
+        .iter()
+        .filter(|event| event.ray == 1500)
+        .collect();
+    let detector = path.iter().find(|event| event.outgoing.is_none()).unwrap();
+    assert_eq!(detector.mirror, 4);
+    assert_eq!(detector.bounce, 2);
+    // every hit on the glass splits into a reflected and a refracted ray, and glass
+    // without absorption loses nothing
+    let entry: Vec<_> = path.iter().filter(|event| event.bounce == 0).collect();
+    assert_eq!(entry.len(), 2);
+    let split: f64 = entry.iter().map(|event| event.outgoing_energy).sum();
+    assert!((split - entry[0].energy).abs() < 1e-12);
+}
+
+#[test]
+fn rough_rooms_scatter_the_same_way_for_the_same_seed() {
+    let scene = Scene::load("assets/room.json", [800.0, 800.0]).unwrap();
+    // every tenth ray of the lamp is plenty here
+    let rays: Vec<Ray> = scene
+        .emit(10, [400.0, 400.0])
+        .into_iter()
+        .step_by(10)
+        .collect();
+    let serial = Tracer {
+        parallel: false,
+        max_bounces: 6,
+        seed: 7,
+        ..Tracer::default()
+    };
+    let trace = serial.trace(&scene, rays.clone());
+    // the light spreads all over the room, past the wall the lamp cannot see around
+    assert_eq!(trace.bounces, 6);
+    assert!(trace
+        .segments
+        .iter()
+        .any(|segment| segment.coords[2] > 450.0 && segment.coords[3] > 500.0));
+
+    let parallel = Tracer {
+        parallel: true,
+        ..serial
+    };
+    assert_eq!(parallel.trace(&scene, rays.clone()), trace);
+    let reseeded = Tracer { seed: 8, ..serial };
+    assert_ne!(reseeded.trace(&scene, rays).segments, trace.segments);
+}
+
+#[test]
+fn interferometer_splits_every_ray_onto_both_detectors() {
+    let scene = Scene::load("assets/interferometer.json", [800.0, 800.0]).unwrap();
+    let rays = scene.emit(10, [400.0, 400.0]);
+    assert_eq!(rays.len(), 50);
+    let trace = Tracer::new().trace(&scene, rays);
+    // both arms reach the second splitter, which splits each of them again
+    assert_eq!(trace.detector_hits.len(), 4 * 50);
+    assert!(trace.remaining_rays.is_empty());
+    for detector in [4, 5] {
+        let energy: f64 = trace
+            .detector_hits
+            .iter()
+            .filter(|hit| hit.detector == detector)
+            .map(|hit| hit.energy)
+            .sum();
+        assert!((energy - 25.0).abs() < 1e-9, "{}", energy);
+    }
+
+    // capped at one ray per light ray, each splitter drops half of what it sends off
+    let capped = Tracer {
+        max_rays: 50,
+        ..Tracer::new()
+    };
+    let trace = capped.trace(&scene, scene.emit(10, [400.0, 400.0]));
+    assert_eq!(trace.detector_hits.len(), 50);
+    assert_eq!(trace.pruned_rays, 100);
+    let energy: f64 = trace.detector_hits.iter().map(|hit| hit.energy).sum();
+    assert!((energy - 12.5).abs() < 1e-9, "{}", energy);
+    assert!((energy + trace.pruned_energy - 50.0).abs() < 1e-9);
+    let serial = Tracer {
+        parallel: false,
+        ..capped
+    };
+    assert_eq!(serial.trace(&scene, scene.emit(10, [400.0, 400.0])), trace);
+}